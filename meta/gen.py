@@ -18,7 +18,7 @@ def decorate(text, pre):
 example = read_file("examples/example.rs")
 parts = read_file("src/lib.rs").split("// lib\n")
 
-lib = "//!\n//! # Examples\n//!\n//!\n//!```\n"
+lib = "//!\n//! # Examples\n//!\n//!\n//!```no_run\n"
 lib = lib + decorate(example, "//!") + "\n//!```\n\n\n// lib\n" + parts[1]
 # print(lib)
 write_file("src/lib.rs", lib)
@@ -0,0 +1,446 @@
+use log::{info, log_enabled, Level};
+
+use uciengine::uciengine::{GoJob, UciEngine};
+
+use crate::archive::*;
+use crate::book::score_cp;
+use crate::game::*;
+use crate::movesource::spawn_engine;
+
+/// evals are capped to this many centipawns before computing the loss of a move
+pub const ANALYSIS_MAX_CP: i32 = 1000;
+
+/// min centipawn loss of an inaccuracy
+pub const INACCURACY_LOSS: i32 = 50;
+
+/// min centipawn loss of a mistake
+pub const MISTAKE_LOSS: i32 = 100;
+
+/// min centipawn loss of a blunder
+pub const BLUNDER_LOSS: i32 = 300;
+
+/// move sources counted as book
+pub const BOOK_SOURCES: [&str; 2] = ["book", "repertoire"];
+
+/// judgement of a move by its centipawn loss
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MoveJudgement {
+    /// loss below INACCURACY_LOSS
+    Good,
+    /// loss below MISTAKE_LOSS
+    Inaccuracy,
+    /// loss below BLUNDER_LOSS
+    Mistake,
+    /// loss from BLUNDER_LOSS
+    Blunder,
+}
+
+/// implementation of move judgement
+impl MoveJudgement {
+    /// judgement of centipawn loss
+    pub fn from_loss(loss: i32) -> MoveJudgement {
+        match loss {
+            loss if loss >= BLUNDER_LOSS => MoveJudgement::Blunder,
+            loss if loss >= MISTAKE_LOSS => MoveJudgement::Mistake,
+            loss if loss >= INACCURACY_LOSS => MoveJudgement::Inaccuracy,
+            _ => MoveJudgement::Good,
+        }
+    }
+
+    /// lower case name
+    pub fn name(&self) -> &'static str {
+        match self {
+            MoveJudgement::Good => "good",
+            MoveJudgement::Inaccuracy => "inaccuracy",
+            MoveJudgement::Mistake => "mistake",
+            MoveJudgement::Blunder => "blunder",
+        }
+    }
+
+    /// numeric annotation glyph, None for good moves
+    pub fn nag(&self) -> Option<u8> {
+        match self {
+            MoveJudgement::Good => None,
+            MoveJudgement::Inaccuracy => Some(6),
+            MoveJudgement::Mistake => Some(2),
+            MoveJudgement::Blunder => Some(4),
+        }
+    }
+}
+
+/// analysis of a move of the bot, scores are centipawns from the bot's point of view
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveAnalysis {
+    /// number of moves played before the move
+    pub ply: usize,
+    /// move in uci notation
+    pub uci: String,
+    /// name of move source
+    pub source: String,
+    /// best move of the analysis
+    pub best: String,
+    /// score of the best move
+    pub best_score: i32,
+    /// score of the move
+    pub score: i32,
+    /// centipawn loss, best score minus score, both capped to ANALYSIS_MAX_CP
+    pub loss: i32,
+    /// judgement
+    pub judgement: MoveJudgement,
+}
+
+/// implementation of move analysis
+impl MoveAnalysis {
+    /// create move analysis of scores, judged by their capped difference
+    pub fn new<U, S, B>(
+        ply: usize,
+        uci: U,
+        source: S,
+        best: B,
+        best_score: i32,
+        score: i32,
+    ) -> MoveAnalysis
+    where
+        U: core::fmt::Display,
+        S: core::fmt::Display,
+        B: core::fmt::Display,
+    {
+        let cap = |score: i32| score.clamp(-ANALYSIS_MAX_CP, ANALYSIS_MAX_CP);
+
+        let loss = (cap(best_score) - cap(score)).max(0);
+
+        MoveAnalysis {
+            ply,
+            uci: uci.to_string(),
+            source: source.to_string(),
+            best: best.to_string(),
+            best_score,
+            score,
+            loss,
+            judgement: MoveJudgement::from_loss(loss),
+        }
+    }
+}
+
+/// string as json string
+fn json_string<T>(value: T) -> String
+where
+    T: core::fmt::Display,
+{
+    let mut json = String::from("\"");
+
+    for c in value.to_string().chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+
+    json.push('"');
+
+    json
+}
+
+/// analysis of the moves of the bot in a game
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameAnalysis {
+    /// game id
+    pub game_id: String,
+    /// engine name
+    pub engine: String,
+    /// search depth
+    pub depth: usize,
+    /// analyzed moves of the bot
+    pub moves: Vec<MoveAnalysis>,
+    /// first move of the bot not taken from a book or repertoire, as ply and move source
+    pub out_of_book: Option<(usize, String)>,
+}
+
+/// implementation of game analysis
+impl GameAnalysis {
+    /// average centipawn loss, None without moves
+    pub fn average_loss(&self) -> Option<f64> {
+        match self.moves.len() {
+            0 => None,
+            n => Some(self.moves.iter().map(|m| m.loss as f64).sum::<f64>() / n as f64),
+        }
+    }
+
+    /// number of moves with judgement
+    pub fn count(&self, judgement: MoveJudgement) -> usize {
+        self.moves
+            .iter()
+            .filter(|m| m.judgement == judgement)
+            .count()
+    }
+
+    /// notes of analyzed moves by ply, for an annotated pgn
+    pub fn notes(&self) -> std::collections::HashMap<usize, MoveNote> {
+        self.moves
+            .iter()
+            .map(|m| {
+                let mut items: Vec<String> = vec![];
+
+                if let Some((ply, source)) = &self.out_of_book {
+                    if *ply == m.ply {
+                        items.push(format!("out of book, {} takes over", source));
+                    }
+                }
+
+                if m.judgement != MoveJudgement::Good {
+                    items.push(format!(
+                        "{} , best {} , loss {} cp",
+                        m.judgement.name(),
+                        m.best,
+                        m.loss
+                    ));
+                }
+
+                (
+                    m.ply,
+                    MoveNote {
+                        nag: m.judgement.nag(),
+                        text: items.join(" , "),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// report as json
+    pub fn to_json(&self) -> String {
+        let moves: Vec<String> = self
+            .moves
+            .iter()
+            .map(|m| {
+                format!(
+                    "{{\"ply\":{},\"uci\":{},\"source\":{},\"best\":{},\"best_score\":{},\"score\":{},\"loss\":{},\"judgement\":{}}}",
+                    m.ply,
+                    json_string(&m.uci),
+                    json_string(&m.source),
+                    json_string(&m.best),
+                    m.best_score,
+                    m.score,
+                    m.loss,
+                    json_string(m.judgement.name())
+                )
+            })
+            .collect();
+
+        let out_of_book = match &self.out_of_book {
+            Some((ply, source)) => {
+                format!("{{\"ply\":{},\"source\":{}}}", ply, json_string(source))
+            }
+            _ => "null".to_string(),
+        };
+
+        let average_loss = match self.average_loss() {
+            Some(average_loss) => format!("{:.1}", average_loss),
+            _ => "null".to_string(),
+        };
+
+        format!(
+            "{{\"game_id\":{},\"engine\":{},\"depth\":{},\"average_loss\":{},\"inaccuracies\":{},\"mistakes\":{},\"blunders\":{},\"out_of_book\":{},\"moves\":[{}]}}\n",
+            json_string(&self.game_id),
+            json_string(&self.engine),
+            self.depth,
+            average_loss,
+            self.count(MoveJudgement::Inaccuracy),
+            self.count(MoveJudgement::Mistake),
+            self.count(MoveJudgement::Blunder),
+            out_of_book,
+            moves.join(",")
+        )
+    }
+}
+
+/// analyzes the moves of the bot after a game with a fixed depth engine search,
+/// the engine runs with a single thread, not to slow down games played at the same time
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameAnalyzer {
+    /// engine executable name
+    pub engine_name: String,
+    /// uci options
+    pub uci_options: std::collections::HashMap<String, String>,
+    /// search depth
+    pub depth: usize,
+    /// folder reports are written to
+    pub dir: String,
+}
+
+/// implementation of game analyzer
+impl GameAnalyzer {
+    /// create game analyzer, Threads is set to 1
+    pub fn new<E, D>(
+        engine_name: E,
+        mut uci_options: std::collections::HashMap<String, String>,
+        depth: usize,
+        dir: D,
+    ) -> GameAnalyzer
+    where
+        E: core::fmt::Display,
+        D: core::fmt::Display,
+    {
+        uci_options.insert("Threads".to_string(), "1".to_string());
+
+        GameAnalyzer {
+            engine_name: engine_name.to_string(),
+            uci_options,
+            depth,
+            dir: dir.to_string(),
+        }
+    }
+
+    /// best move and score from the point of view of the side to move after moves
+    async fn search(
+        &self,
+        engine: &UciEngine,
+        info: &GameInfo,
+        moves: &[&str],
+        set_options: bool,
+    ) -> Result<(String, i32), String> {
+        let mut go_job = match info.initial_fen.as_str() {
+            "startpos" => GoJob::new().pos_startpos(),
+            fen => GoJob::new().pos_fen(fen),
+        }
+        .go_opt("depth", self.depth);
+
+        if !moves.is_empty() {
+            go_job = go_job.pos_moves(moves.join(" "));
+        }
+
+        if set_options {
+            for (key, value) in &self.uci_options {
+                go_job = go_job.uci_opt(key, value);
+            }
+        }
+
+        let go_result = engine
+            .go(go_job)
+            .await
+            .map_err(|err| format!("engine failed {:?}", err))?;
+
+        Ok((
+            go_result.bestmove.unwrap_or_default(),
+            score_cp(&go_result.ai.score),
+        ))
+    }
+
+    /// analysis of move decision, uci options are set with the first search
+    async fn analyze_move(
+        &self,
+        engine: &UciEngine,
+        record: &GameRecord,
+        moves: &[&str],
+        decision: &MoveDecision,
+        set_options: bool,
+    ) -> Result<MoveAnalysis, String> {
+        let before = &moves[..decision.ply];
+
+        let (best, best_score) = self
+            .search(engine, &record.info, before, set_options)
+            .await?;
+
+        let score = match best == decision.uci {
+            true => best_score,
+            _ => {
+                let after = &moves[..decision.ply + 1];
+
+                -self.search(engine, &record.info, after, false).await?.1
+            }
+        };
+
+        Ok(MoveAnalysis::new(
+            decision.ply,
+            &decision.uci,
+            &decision.source,
+            best,
+            best_score,
+            score,
+        ))
+    }
+
+    /// analysis of the moves of the bot in game
+    pub async fn analyze(&self, record: &GameRecord) -> Result<GameAnalysis, String> {
+        let engine = spawn_engine(&self.engine_name)
+            .ok_or(format!("could not start {}", self.engine_name))?;
+
+        let moves: Vec<&str> = record.moves.split_whitespace().collect();
+
+        let mut analysis = GameAnalysis {
+            game_id: record.info.id.to_owned(),
+            engine: self.engine_name.to_owned(),
+            depth: self.depth,
+            moves: vec![],
+            out_of_book: record
+                .decisions
+                .iter()
+                .find(|decision| !BOOK_SOURCES.contains(&decision.source.as_str()))
+                .map(|decision| (decision.ply, decision.source.to_owned())),
+        };
+
+        let mut result = Ok(());
+
+        for decision in record.decisions.iter() {
+            if decision.ply >= moves.len() {
+                continue;
+            }
+
+            match self
+                .analyze_move(&engine, record, &moves, decision, analysis.moves.is_empty())
+                .await
+            {
+                Ok(move_analysis) => analysis.moves.push(move_analysis),
+                Err(err) => {
+                    result = Err(err);
+
+                    break;
+                }
+            }
+        }
+
+        engine.quit();
+
+        result.map(|_| analysis)
+    }
+
+    /// paths of the json report and the annotated pgn of game, {dir}/{yyyy-mm}/{game id}.json and .pgn
+    pub fn paths(&self, info: &GameInfo) -> (std::path::PathBuf, std::path::PathBuf) {
+        let dir = std::path::Path::new(&self.dir).join(info.created_at.format("%Y-%m").to_string());
+
+        (
+            dir.join(format!("{}.json", info.id)),
+            dir.join(format!("{}.pgn", info.id)),
+        )
+    }
+
+    /// analyze game and write the json report and the annotated pgn, failures are only logged
+    pub async fn report<E>(&self, record: GameRecord, outcome: GameOutcome, engine_name: E)
+    where
+        E: core::fmt::Display,
+    {
+        let result = match self.analyze(&record).await {
+            Ok(analysis) => {
+                let (json_path, pgn_path) = self.paths(&record.info);
+
+                let pgn = noted_game_pgn(&record, &outcome, engine_name, None, &analysis.notes());
+
+                json_path
+                    .parent()
+                    .map(std::fs::create_dir_all)
+                    .unwrap_or(Ok(()))
+                    .and_then(|_| std::fs::write(&json_path, analysis.to_json()))
+                    .and_then(|_| std::fs::write(&pgn_path, pgn))
+                    .map(|_| json_path)
+                    .map_err(|err| format!("{:?}", err))
+            }
+            Err(err) => Err(err),
+        };
+
+        if log_enabled!(Level::Info) {
+            info!("analysis of game {} : {:?}", record.info.id, result);
+        }
+    }
+}
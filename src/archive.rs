@@ -0,0 +1,371 @@
+use log::{info, log_enabled, Level};
+
+use shakmaty::fen::Fen;
+use shakmaty::san::SanPlus;
+use shakmaty::uci::Uci;
+use shakmaty::{CastlingMode, Chess};
+
+use std::io::Write;
+
+use uciengine::analysis::Score;
+
+use crate::game::*;
+
+/// max length of a pgn movetext line
+pub const PGN_LINE_LENGTH: usize = 80;
+
+/// opening of a game, as named by lichess
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameOpening {
+    /// eco code
+    pub eco: String,
+    /// name
+    pub name: String,
+}
+
+/// implementation of game opening
+impl GameOpening {
+    /// opening from the ECO and Opening headers of a pgn, None if they are missing
+    pub fn from_pgn<T>(pgn: T) -> Option<GameOpening>
+    where
+        T: core::fmt::Display,
+    {
+        let pgn = pgn.to_string();
+
+        Some(GameOpening {
+            eco: pgn_header(&pgn, "ECO")?,
+            name: pgn_header(&pgn, "Opening")?,
+        })
+    }
+}
+
+/// value of pgn header, None if missing
+pub fn pgn_header<T, N>(pgn: T, name: N) -> Option<String>
+where
+    T: core::fmt::Display,
+    N: core::fmt::Display,
+{
+    let prefix = format!("[{} \"", name);
+
+    pgn.to_string().lines().find_map(|line| {
+        line.trim()
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix("\"]"))
+            .map(|value| value.to_string())
+    })
+}
+
+/// time in milliseconds as h:mm:ss
+pub fn clock_text(time: usize) -> String {
+    let seconds = time / 1000;
+
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// time in milliseconds as h:mm:ss.t
+pub fn elapsed_text(time: usize) -> String {
+    format!("{}.{}", clock_text(time), time % 1000 / 100)
+}
+
+/// score from the bot's point of view as pgn eval from white's point of view
+pub fn eval_text(score: &Score, bot_white: bool) -> String {
+    let sign = match bot_white {
+        true => 1,
+        _ => -1,
+    };
+
+    match score {
+        Score::Cp(cp) => format!("{:.2}", (sign * cp) as f64 / 100.0),
+        Score::Mate(n) => format!("#{}", sign * n),
+    }
+}
+
+/// pgn time control, - for unlimited games
+pub fn time_control_text(info: &GameInfo) -> String {
+    match info.time_control {
+        Some((initial, increment)) => format!("{}+{}", initial / 1000, increment / 1000),
+        _ => "-".to_string(),
+    }
+}
+
+/// pgn termination of outcome
+fn termination_text(outcome: &GameOutcome) -> &'static str {
+    match outcome.termination {
+        Termination::OutOfTime => "Time forfeit",
+        Termination::Timeout | Termination::Aborted | Termination::NoStart => "Abandoned",
+        Termination::Cheat => "Rules infraction",
+        Termination::UnknownFinish => "Unterminated",
+        _ => "Normal",
+    }
+}
+
+/// note added to a move of a pgn, like an analysis result
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveNote {
+    /// numeric annotation glyph, like 2 for ? or 4 for ??
+    pub nag: Option<u8>,
+    /// text put in front of the move comment
+    pub text: String,
+}
+
+/// comment of move at ply, note, source, depth, eval, move time and clock of the bot's moves,
+/// clock of the opponent's moves
+fn move_comment(record: &GameRecord, ply: usize, note: Option<&MoveNote>) -> Option<String> {
+    let mut items: Vec<String> = vec![];
+
+    if let Some(note) = note.filter(|note| !note.text.is_empty()) {
+        items.push(note.text.replace('}', ")"));
+    }
+
+    if let Some(decision) = record.decision_at(ply) {
+        items.push(decision.source.to_owned());
+
+        if let Some(depth) = decision.depth {
+            items.push(format!("depth {}", depth));
+        }
+
+        if let Some(score) = &decision.score {
+            items.push(format!(
+                "[%eval {}]",
+                eval_text(score, record.info.bot_white)
+            ));
+        }
+
+        items.push(format!("[%emt {}]", elapsed_text(decision.time)));
+    }
+
+    if let Some(Some(clock)) = record.clocks.get(ply) {
+        items.push(format!("[%clk {}]", clock_text(*clock)));
+    }
+
+    match items.is_empty() {
+        true => None,
+        _ => Some(format!("{{ {} }}", items.join(" "))),
+    }
+}
+
+/// tokens joined in lines of at most PGN_LINE_LENGTH characters, unless a token is longer
+fn wrap(tokens: Vec<String>) -> String {
+    let mut lines: Vec<String> = vec![];
+
+    let mut line = String::new();
+
+    for token in tokens {
+        if !line.is_empty() && (line.len() + 1 + token.len() > PGN_LINE_LENGTH) {
+            lines.push(std::mem::take(&mut line));
+        }
+
+        if !line.is_empty() {
+            line.push(' ');
+        }
+
+        line.push_str(&token);
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines.join("\n")
+}
+
+/// annotated pgn of finished game, played with engine, moves after an illegal move are left out
+pub fn game_pgn<E>(
+    record: &GameRecord,
+    outcome: &GameOutcome,
+    engine_name: E,
+    opening: Option<&GameOpening>,
+) -> String
+where
+    E: core::fmt::Display,
+{
+    noted_game_pgn(
+        record,
+        outcome,
+        engine_name,
+        opening,
+        &std::collections::HashMap::new(),
+    )
+}
+
+/// annotated pgn of finished game with notes of moves by ply, see game_pgn
+pub fn noted_game_pgn<E>(
+    record: &GameRecord,
+    outcome: &GameOutcome,
+    engine_name: E,
+    opening: Option<&GameOpening>,
+    notes: &std::collections::HashMap<usize, MoveNote>,
+) -> String
+where
+    E: core::fmt::Display,
+{
+    let info = &record.info;
+
+    let rating = |player: &Player| {
+        player
+            .rating
+            .map(|rating| rating.to_string())
+            .unwrap_or_else(|| "?".to_string())
+    };
+
+    let rated = match info.rated {
+        true => "Rated",
+        _ => "Casual",
+    };
+
+    let mut headers: Vec<(&str, String)> = vec![
+        ("Event", format!("{} {} game", rated, info.speed)),
+        ("Site", format!("https://lichess.org/{}", info.id)),
+        ("Date", info.created_at.format("%Y.%m.%d").to_string()),
+        ("White", info.white.name.to_owned()),
+        ("Black", info.black.name.to_owned()),
+        ("Result", outcome.pgn_result()),
+        ("WhiteElo", rating(&info.white)),
+        ("BlackElo", rating(&info.black)),
+    ];
+
+    if let Some(title) = &info.white.title {
+        headers.push(("WhiteTitle", title.to_owned()));
+    }
+
+    if let Some(title) = &info.black.title {
+        headers.push(("BlackTitle", title.to_owned()));
+    }
+
+    headers.push(("TimeControl", time_control_text(info)));
+    headers.push(("Termination", termination_text(outcome).to_string()));
+    headers.push(("Variant", info.variant.to_owned()));
+
+    if let Some(opening) = opening {
+        headers.push(("ECO", opening.eco.to_owned()));
+        headers.push(("Opening", opening.name.to_owned()));
+    }
+
+    headers.push(("Engine", engine_name.to_string()));
+
+    let mut pos = Chess::default();
+
+    if info.initial_fen != "startpos" {
+        headers.push(("SetUp", "1".to_string()));
+        headers.push(("FEN", info.initial_fen.to_owned()));
+
+        if let Ok(setup) = info.initial_fen.parse::<Fen>() {
+            if let Ok(initial_pos) = setup.position(CastlingMode::Standard) {
+                pos = initial_pos;
+            }
+        }
+    }
+
+    let mut tokens: Vec<String> = vec![];
+
+    let first_move_number = match info.initial_fen.split_whitespace().nth(5) {
+        Some(number) => number.parse().unwrap_or(1),
+        _ => 1,
+    };
+
+    let white_starts = info.white_starts();
+
+    for (ply, uci) in record.moves.split_whitespace().enumerate() {
+        let m = match uci
+            .parse::<Uci>()
+            .ok()
+            .and_then(|uci| uci.to_move(&pos).ok())
+        {
+            Some(m) => m,
+            _ => break,
+        };
+
+        let white_moves = info.white_moves_at(ply);
+
+        // plies counted from white's first move
+        let full_ply = ply + (!white_starts as usize);
+
+        if white_moves {
+            tokens.push(format!("{}.", first_move_number + full_ply / 2));
+        } else if ply == 0 {
+            tokens.push(format!("{}...", first_move_number + full_ply / 2));
+        }
+
+        tokens.push(SanPlus::from_move_and_play_unchecked(&mut pos, &m).to_string());
+
+        let note = notes.get(&ply);
+
+        if let Some(nag) = note.and_then(|note| note.nag) {
+            tokens.push(format!("${}", nag));
+        }
+
+        if let Some(comment) = move_comment(record, ply, note) {
+            tokens.push(comment);
+        }
+    }
+
+    tokens.push(outcome.pgn_result());
+
+    format!(
+        "{}\n\n{}\n",
+        headers
+            .iter()
+            .map(|(name, value)| format!("[{} \"{}\"]", name, value.replace('"', "'")))
+            .collect::<Vec<String>>()
+            .join("\n"),
+        wrap(tokens)
+    )
+}
+
+/// pgn archive of played games, one file per day in a folder per month
+#[derive(Debug, Clone, PartialEq)]
+pub struct PgnArchive {
+    /// archive folder
+    pub dir: String,
+}
+
+/// implementation of pgn archive
+impl PgnArchive {
+    /// create pgn archive in folder
+    pub fn new<T>(dir: T) -> PgnArchive
+    where
+        T: core::fmt::Display,
+    {
+        PgnArchive {
+            dir: dir.to_string(),
+        }
+    }
+
+    /// path of the file of game, {dir}/{yyyy-mm}/{yyyy-mm-dd}.pgn by creation date
+    pub fn path(&self, info: &GameInfo) -> std::path::PathBuf {
+        std::path::Path::new(&self.dir)
+            .join(info.created_at.format("%Y-%m").to_string())
+            .join(format!("{}.pgn", info.created_at.format("%Y-%m-%d")))
+    }
+
+    /// append pgn of game to its file, returns path of the file
+    pub fn save<T>(&self, info: &GameInfo, pgn: T) -> Result<std::path::PathBuf, String>
+    where
+        T: core::fmt::Display,
+    {
+        let path = self.path(info);
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|err| format!("{:?}", err))?;
+        }
+
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|err| format!("{:?}", err))?;
+
+        writeln!(file, "{}", pgn).map_err(|err| format!("{:?}", err))?;
+
+        if log_enabled!(Level::Info) {
+            info!("game {} saved to {:?}", info.id, path);
+        }
+
+        Ok(path)
+    }
+}
@@ -0,0 +1,473 @@
+//! Build, inspect and probe opening books offline.
+//!
+//! - `lichessbot-book build [options] --output {book.pgn or book.bin} {games.pgn} ...`
+//! - `lichessbot-book stats [--max-ply {n}] {book}`
+//! - `lichessbot-book probe [--max-ply {n}] {book} {fen}`
+//!
+//! `build` reads games from PGN files and writes a PGN book, the games truncated to the book moves,
+//! or a Polyglot book weighted by the number of plays, depending on the extension of the output.
+//!
+//! build options
+//!
+//! - `--player {names}` : games of these players only
+//! - `--min-elo {elo}` : games with both players rated at least this
+//! - `--time-control {list}` : games with these time controls ( `180+2` ) or speeds ( `blitz` )
+//! - `--result {list}` : games with these results ( `1-0`, `0-1`, `1/2-1/2` ), or `win`, `draw`, `loss` of the players
+//! - `--max-ply {n}` : number of moves of a game in the book, default 20
+//! - `--min-count {n}` : min number of plays of a move in a position, default 1
+//!
+//! Lists are comma separated. `stats` and `probe` take any book the bot can use, `.pgn`, `.bin` or `.learn`.
+
+use pgnparse::parser::*;
+
+use shakmaty::fen::{epd, Fen};
+use shakmaty::san::San;
+use shakmaty::uci::Uci;
+use shakmaty::{CastlingMode, Chess};
+
+use lichessbot::book::*;
+use lichessbot::polyglot::*;
+
+/// default number of moves of a game in the book
+const DEFAULT_MAX_PLY: usize = 20;
+
+/// usage
+const USAGE: &str = "usage
+
+lichessbot-book build [--player names] [--min-elo elo] [--time-control list] [--result list]
+                      [--max-ply n] [--min-count n] --output book.pgn|book.bin games.pgn ...
+lichessbot-book stats [--max-ply n] book
+lichessbot-book probe [--max-ply n] book fen";
+
+/// game filter
+#[derive(Debug, Clone)]
+struct Filter {
+    /// player names, lower case
+    players: Vec<String>,
+    /// min elo of both players
+    min_elo: Option<usize>,
+    /// time controls or speeds
+    time_controls: Vec<String>,
+    /// results
+    results: Vec<String>,
+    /// number of moves of a game in the book
+    max_ply: usize,
+    /// min number of plays of a move in a position
+    min_count: usize,
+}
+
+/// comma separated list to lower case names
+fn list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// header of game, ? if missing
+fn header(game: &PgnInfo, key: &str) -> String {
+    game.headers
+        .get(key)
+        .cloned()
+        .unwrap_or_else(|| "?".to_string())
+}
+
+/// lichess speed of a time control header, base seconds + 40 x increment
+fn speed(time_control: &str) -> &'static str {
+    let mut parts = time_control.split('+');
+
+    let base: usize = match parts.next().and_then(|base| base.parse().ok()) {
+        Some(base) => base,
+        _ => return "correspondence",
+    };
+
+    let inc: usize = parts.next().and_then(|inc| inc.parse().ok()).unwrap_or(0);
+
+    match base + 40 * inc {
+        duration if duration < 30 => "ultrabullet",
+        duration if duration < 180 => "bullet",
+        duration if duration < 480 => "blitz",
+        duration if duration < 1500 => "rapid",
+        _ => "classical",
+    }
+}
+
+/// implementation of filter
+impl Filter {
+    /// true if game passes the filter
+    fn accepts(&self, game: &PgnInfo) -> bool {
+        let variant = header(game, "Variant").to_lowercase();
+
+        if game.moves.is_empty() || !(variant == "?" || variant == "standard") {
+            return false;
+        }
+
+        let white = header(game, "White").to_lowercase();
+        let black = header(game, "Black").to_lowercase();
+
+        if !(self.players.is_empty()
+            || self.players.contains(&white)
+            || self.players.contains(&black))
+        {
+            return false;
+        }
+
+        if let Some(min_elo) = self.min_elo {
+            for elo_header in &["WhiteElo", "BlackElo"] {
+                match header(game, elo_header).parse::<usize>() {
+                    Ok(elo) if elo >= min_elo => {}
+                    _ => return false,
+                }
+            }
+        }
+
+        if !self.time_controls.is_empty() {
+            let time_control = header(game, "TimeControl");
+
+            if !(self.time_controls.contains(&time_control)
+                || self
+                    .time_controls
+                    .contains(&speed(&time_control).to_string()))
+            {
+                return false;
+            }
+        }
+
+        if !self.results.is_empty() {
+            let result = header(game, "Result");
+
+            let player_result = match (result.as_str(), self.players.contains(&white)) {
+                ("1/2-1/2", _) => "draw",
+                ("1-0", true) | ("0-1", false) => "win",
+                _ => "loss",
+            };
+
+            let player_result_counts = !self.players.is_empty() && (result != "*");
+
+            if !(self.results.contains(&result)
+                || (player_result_counts && self.results.contains(&player_result.to_string())))
+            {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// book moves of game, epd before and uci
+    fn book_moves(&self, game: &PgnInfo) -> Vec<(String, String)> {
+        game.moves
+            .iter()
+            .take(self.max_ply)
+            .map(|m| (m.epd_before.to_owned(), m.uci.to_owned()))
+            .collect()
+    }
+}
+
+/// games of pgn files that pass the filter
+fn games<'a>(files: &'a [String], filter: &'a Filter) -> impl Iterator<Item = PgnInfo> + 'a {
+    files
+        .iter()
+        .filter_map(|file| {
+            let iter = PgnIterator::new(file);
+
+            if iter.is_none() {
+                eprintln!("could not open {}", file);
+            }
+
+            iter
+        })
+        .flatten()
+        .map(parse_pgn_to_rust_struct)
+        .filter(move |game| filter.accepts(game))
+}
+
+/// position of epd
+fn epd_position(epd: &str) -> Option<Chess> {
+    format!("{} 0 1", epd)
+        .parse::<Fen>()
+        .ok()?
+        .position(CastlingMode::Standard)
+        .ok()
+}
+
+/// write pgn of game truncated to ply moves
+fn write_game<W>(out: &mut W, game: &PgnInfo, ply: usize) -> std::io::Result<()>
+where
+    W: std::io::Write,
+{
+    let result = header(game, "Result");
+
+    let mut keys: Vec<&String> = game.headers.keys().collect();
+
+    keys.sort();
+
+    for key in keys {
+        writeln!(out, "[{} \"{}\"]", key, game.headers[key])?;
+    }
+
+    writeln!(out)?;
+
+    let mut moves = String::new();
+
+    for (i, m) in game.moves.iter().take(ply).enumerate() {
+        if i % 2 == 0 {
+            moves += &format!("{}. ", i / 2 + 1);
+        }
+
+        moves += &format!("{} ", m.san);
+    }
+
+    writeln!(out, "{}{}", moves, result)?;
+    writeln!(out)
+}
+
+/// build book
+fn build(filter: &Filter, files: &[String], output: &str) -> Result<(), String> {
+    // number of plays of a move, by epd and uci
+    let mut plays: std::collections::HashMap<(String, String), usize> =
+        std::collections::HashMap::new();
+
+    let mut accepted = 0;
+
+    for game in games(files, filter) {
+        accepted += 1;
+
+        for key in filter.book_moves(&game) {
+            *plays.entry(key).or_default() += 1;
+        }
+    }
+
+    plays.retain(|_, count| *count >= filter.min_count);
+
+    let positions: std::collections::HashSet<&String> = plays.keys().map(|(epd, _)| epd).collect();
+
+    println!(
+        "games accepted {}, positions {}, moves {}",
+        accepted,
+        positions.len(),
+        plays.len()
+    );
+
+    match output.to_lowercase().ends_with(".bin") {
+        true => {
+            let mut book = PolyglotBook::new();
+
+            for ((epd, uci), count) in &plays {
+                if let Some(pos) = epd_position(epd) {
+                    if let Some(mv) = encode_move(&pos, uci) {
+                        book.entries.push(PolyglotEntry {
+                            key: polyglot_key(&pos),
+                            mv,
+                            weight: (*count).min(u16::MAX as usize) as u16,
+                            learn: 0,
+                        });
+                    }
+                }
+            }
+
+            book.save(output).map_err(|err| err.to_string())?;
+
+            println!("polyglot entries written {}", book.entries.len());
+        }
+        _ => {
+            let file = std::fs::File::create(output).map_err(|err| err.to_string())?;
+
+            let mut out = std::io::BufWriter::new(file);
+
+            let mut written = 0;
+
+            // games are cut at the first move below the min count, so the book has only those moves
+            for game in games(files, filter) {
+                let ply = filter
+                    .book_moves(&game)
+                    .into_iter()
+                    .take_while(|key| plays.contains_key(key))
+                    .count();
+
+                if ply > 0 {
+                    write_game(&mut out, &game, ply).map_err(|err| err.to_string())?;
+
+                    written += 1;
+                }
+            }
+
+            println!("pgn games written {}", written);
+        }
+    }
+
+    Ok(())
+}
+
+/// print statistics of book
+fn stats(book: &OpeningBook) {
+    match book {
+        OpeningBook::Pgn(book) => {
+            let moves: usize = book.positions.values().map(|pos| pos.moves.len()).sum();
+            let plays: usize = book.positions.values().map(|pos| pos.total_plays()).sum();
+
+            println!(
+                "pgn book, positions {}, moves {}, plays {}",
+                book.positions.len(),
+                moves,
+                plays
+            );
+        }
+        OpeningBook::Polyglot(book) => {
+            let positions: std::collections::HashSet<u64> =
+                book.entries.iter().map(|entry| entry.key).collect();
+
+            println!(
+                "polyglot book, positions {}, entries {}",
+                positions.len(),
+                book.entries.len()
+            );
+        }
+        OpeningBook::Learning(book) => {
+            if let Ok(book) = book.read() {
+                let moves: Vec<&lichessbot::learn::LearnedMove> = book
+                    .positions
+                    .values()
+                    .flat_map(|moves| moves.values())
+                    .collect();
+
+                println!(
+                    "learning book, positions {}, moves {}, games {}",
+                    book.positions.len(),
+                    moves.len(),
+                    moves.iter().map(|m| m.games).sum::<usize>()
+                );
+            }
+        }
+    }
+}
+
+/// print candidate moves of fen
+fn probe(book: &OpeningBook, fen: &str) -> Result<(), String> {
+    let pos: Chess = fen
+        .parse::<Fen>()
+        .map_err(|err| err.to_string())?
+        .position(CastlingMode::Standard)
+        .map_err(|err| err.to_string())?;
+
+    let mut candidates = book.candidates(&pos, &epd(&pos));
+
+    candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.weight));
+
+    let total: usize = candidates.iter().map(|candidate| candidate.weight).sum();
+
+    if candidates.is_empty() {
+        println!("position is not in book");
+    }
+
+    for candidate in candidates {
+        let san = candidate
+            .uci
+            .parse::<Uci>()
+            .ok()
+            .and_then(|uci| uci.to_move(&pos).ok())
+            .map(|m| San::from_move(&pos, &m).to_string())
+            .unwrap_or_else(|| "?".to_string());
+
+        let share = match total {
+            0 => 0,
+            _ => (candidate.weight * 100) / total,
+        };
+
+        let perf = candidate
+            .perf
+            .map(|perf| format!(" perf {}%", perf))
+            .unwrap_or_default();
+
+        println!(
+            "{:8} {:6} weight {:6} ( {}% ){}",
+            san, candidate.uci, candidate.weight, share, perf
+        );
+    }
+
+    Ok(())
+}
+
+/// parse arguments and run command
+fn run(args: Vec<String>) -> Result<(), String> {
+    let mut filter = Filter {
+        players: vec![],
+        min_elo: None,
+        time_controls: vec![],
+        results: vec![],
+        max_ply: DEFAULT_MAX_PLY,
+        min_count: 1,
+    };
+
+    let mut output: Option<String> = None;
+    let mut positional: Vec<String> = vec![];
+
+    let mut args = args.into_iter();
+
+    let command = args.next().ok_or("missing command")?;
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            positional.push(arg);
+
+            continue;
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value of {}", arg))?;
+
+        let number = |value: &str| {
+            value
+                .parse::<usize>()
+                .map_err(|_| format!("{} needs a number", arg))
+        };
+
+        match arg.as_str() {
+            "--player" => filter.players = list(&value),
+            "--min-elo" => filter.min_elo = Some(number(&value)?),
+            "--time-control" => filter.time_controls = list(&value),
+            "--result" => filter.results = list(&value),
+            "--max-ply" => filter.max_ply = number(&value)?,
+            "--min-count" => filter.min_count = number(&value)?,
+            "--output" => output = Some(value),
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+
+    match command.as_str() {
+        "build" => {
+            let output = output.ok_or("missing --output")?;
+
+            if positional.is_empty() {
+                return Err("missing pgn files".to_string());
+            }
+
+            build(&filter, &positional, &output)
+        }
+        "stats" | "probe" => {
+            let path = positional.first().ok_or("missing book")?;
+
+            let book = OpeningBook::load(path, "", filter.max_ply)?;
+
+            match command.as_str() {
+                "stats" => {
+                    stats(&book);
+
+                    Ok(())
+                }
+                _ => probe(&book, positional.get(1).ok_or("missing fen")?),
+            }
+        }
+        _ => Err(format!("unknown command {}", command)),
+    }
+}
+
+fn main() {
+    if let Err(err) = run(std::env::args().skip(1).collect()) {
+        eprintln!("{}\n\n{}", err, USAGE);
+
+        std::process::exit(1);
+    }
+}
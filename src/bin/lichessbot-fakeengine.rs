@@ -0,0 +1,340 @@
+//! Scripted fake UCI engine for deterministic tests.
+//!
+//! Every `go` consumes the next entry of the `Script` option, entries are separated by `;`,
+//! lines of an entry are separated by `|` and sent verbatim, except
+//!
+//! - `delay {ms}` : think for this long before sending the remaining lines ( `stop` cuts it short )
+//! - `crash` : exit the process without answering
+//! - `hang` : never answer, not even to `stop`
+//!
+//! If an entry has no `bestmove` line, or the script is exhausted, the engine answers with
+//! the first legal move of the position ( in uci order ) and the first legal reply as ponder.
+//!
+//! Setting the script again to the same value keeps its position, as a gui may send every option
+//! before each `go`, a different value starts the new script from its first entry.
+//!
+//! `go ponder` keeps the answer until `ponderhit` or `stop`, as required by the UCI protocol.
+//! The `Log` option names a file that every received command is appended to.
+//!
+//! Example script
+//!
+//! `setoption name Script value info depth 5 score cp 20 pv e2e4 e7e5|bestmove e2e4 ponder e7e5;delay 200|bestmove g1f3`
+
+use std::io::{BufRead, Write};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use shakmaty::fen::Fen;
+use shakmaty::uci::Uci;
+use shakmaty::{CastlingMode, Chess, Position};
+
+/// answer to a go command
+struct Response {
+    /// think time in milliseconds
+    delay: u64,
+    /// exit without answering
+    crash: bool,
+    /// never answer
+    hang: bool,
+    /// lines to send
+    lines: Vec<String>,
+}
+
+/// search state of the engine
+enum Search {
+    /// not searching
+    Idle,
+    /// searching, answer is due at deadline
+    Thinking(Instant, Response),
+    /// pondering, answer is kept until ponderhit or stop
+    Pondering(Response),
+}
+
+/// fake engine
+struct FakeEngine {
+    /// value of the script option
+    script_value: String,
+    /// script entries not yet used
+    script: Vec<String>,
+    /// default think time in milliseconds
+    delay: u64,
+    /// command log file
+    log: Option<String>,
+    /// current position
+    pos: Chess,
+}
+
+/// sorted legal moves of a position in uci notation
+fn legal_ucis(pos: &Chess) -> Vec<(String, Chess)> {
+    let mut ucis: Vec<(String, Chess)> = pos
+        .legals()
+        .iter()
+        .map(|m| {
+            let mut after = pos.clone();
+
+            after.play_unchecked(m);
+
+            (Uci::from_standard(m).to_string(), after)
+        })
+        .collect();
+
+    ucis.sort_by(|a, b| a.0.cmp(&b.0));
+
+    ucis
+}
+
+/// send a line to the gui
+fn send<T>(line: T)
+where
+    T: core::fmt::Display,
+{
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+
+    let _ = writeln!(stdout, "{}", line);
+    let _ = stdout.flush();
+}
+
+/// fake engine implementation
+impl FakeEngine {
+    /// create fake engine
+    fn new() -> FakeEngine {
+        FakeEngine {
+            script_value: String::new(),
+            script: vec![],
+            delay: 0,
+            log: None,
+            pos: Chess::default(),
+        }
+    }
+
+    /// default answer, first legal move and first legal reply
+    fn default_lines(&self) -> Vec<String> {
+        let ucis = legal_ucis(&self.pos);
+
+        match ucis.first() {
+            Some((bestmove, after)) => match legal_ucis(after).first() {
+                Some((ponder, _)) => vec![
+                    format!("info depth 1 score cp 0 pv {} {}", bestmove, ponder),
+                    format!("bestmove {} ponder {}", bestmove, ponder),
+                ],
+                _ => vec![
+                    format!("info depth 1 score cp 0 pv {}", bestmove),
+                    format!("bestmove {}", bestmove),
+                ],
+            },
+            _ => vec!["bestmove (none)".to_string()],
+        }
+    }
+
+    /// response for the next go command
+    fn next_response(&mut self) -> Response {
+        let mut response = Response {
+            delay: self.delay,
+            crash: false,
+            hang: false,
+            lines: vec![],
+        };
+
+        if !self.script.is_empty() {
+            let entry = self.script.remove(0);
+
+            for line in entry.split('|').map(|line| line.trim()) {
+                if let Some(delay) = line.strip_prefix("delay ") {
+                    response.delay = delay.trim().parse().unwrap_or(self.delay);
+                } else if line == "crash" {
+                    response.crash = true;
+                } else if line == "hang" {
+                    response.hang = true;
+                } else if !line.is_empty() {
+                    response.lines.push(line.to_string());
+                }
+            }
+        }
+
+        if !response
+            .lines
+            .iter()
+            .any(|line| line.starts_with("bestmove"))
+        {
+            response.lines.extend(self.default_lines());
+        }
+
+        response
+    }
+
+    /// set option
+    fn set_option(&mut self, command: &str) {
+        let rest = command.trim_start_matches("setoption name ");
+
+        let (name, value) = match rest.find(" value ") {
+            Some(index) => (&rest[..index], rest[index + 7..].to_string()),
+            _ => (rest, String::new()),
+        };
+
+        match name {
+            "Script" if value != self.script_value => {
+                self.script = value
+                    .split(';')
+                    .map(|entry| entry.to_string())
+                    .filter(|entry| !entry.trim().is_empty())
+                    .collect();
+
+                self.script_value = value;
+            }
+            "Delay" => self.delay = value.parse().unwrap_or(0),
+            "Log" => self.log = Some(value),
+            _ => {}
+        }
+    }
+
+    /// set position
+    fn set_position(&mut self, command: &str) {
+        let (spec, moves) = match command.find(" moves ") {
+            Some(index) => (&command[..index], &command[index + 7..]),
+            _ => (command, ""),
+        };
+
+        let mut pos = match spec.strip_prefix("position fen ") {
+            Some(fen) => fen
+                .parse::<Fen>()
+                .ok()
+                .and_then(|fen| fen.position(CastlingMode::Standard).ok())
+                .unwrap_or_default(),
+            _ => Chess::default(),
+        };
+
+        for uci in moves.split_whitespace() {
+            if let Some(m) = uci
+                .parse::<Uci>()
+                .ok()
+                .and_then(|uci| uci.to_move(&pos).ok())
+            {
+                pos.play_unchecked(&m);
+            }
+        }
+
+        self.pos = pos;
+    }
+
+    /// append command to log file
+    fn log(&self, command: &str) {
+        if let Some(log) = &self.log {
+            if let Ok(mut file) = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(log)
+            {
+                let _ = writeln!(file, "{}", command);
+            }
+        }
+    }
+}
+
+/// send response lines, crash or hang
+fn answer(response: Response) {
+    if response.crash {
+        std::process::exit(1);
+    }
+
+    if response.hang {
+        return;
+    }
+
+    for line in response.lines {
+        send(line);
+    }
+}
+
+fn main() {
+    let (tx, rx) = mpsc::channel::<String>();
+
+    std::thread::spawn(move || {
+        let stdin = std::io::stdin();
+
+        for line in stdin.lock().lines() {
+            match line {
+                Ok(line) => {
+                    if tx.send(line).is_err() {
+                        break;
+                    }
+                }
+                _ => break,
+            }
+        }
+    });
+
+    let mut engine = FakeEngine::new();
+    let mut search = Search::Idle;
+
+    loop {
+        let received = match &search {
+            Search::Thinking(deadline, _) => {
+                let now = Instant::now();
+
+                if now >= *deadline {
+                    Err(mpsc::RecvTimeoutError::Timeout)
+                } else {
+                    rx.recv_timeout(*deadline - now)
+                }
+            }
+            _ => rx.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+
+        let command = match received {
+            Ok(command) => command.trim().to_string(),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                if let Search::Thinking(_, response) = std::mem::replace(&mut search, Search::Idle)
+                {
+                    answer(response);
+                }
+
+                continue;
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+
+        engine.log(&command);
+
+        if command == "uci" {
+            send("id name lichessbot-fakeengine");
+            send("id author hyperchessbot");
+            send("option name Script type string default <empty>");
+            send("option name Delay type spin default 0 min 0 max 3600000");
+            send("option name Log type string default <empty>");
+            send("uciok");
+        } else if command == "isready" {
+            send("readyok");
+        } else if command.starts_with("setoption name ") {
+            engine.set_option(&command);
+        } else if command == "ucinewgame" {
+            engine.pos = Chess::default();
+        } else if command.starts_with("position ") {
+            engine.set_position(&command);
+        } else if command == "go" || command.starts_with("go ") {
+            let response = engine.next_response();
+
+            search = match command.split_whitespace().any(|token| token == "ponder") {
+                true => Search::Pondering(response),
+                _ => Search::Thinking(
+                    Instant::now() + Duration::from_millis(response.delay),
+                    response,
+                ),
+            };
+        } else if command == "ponderhit" {
+            if let Search::Pondering(response) = std::mem::replace(&mut search, Search::Idle) {
+                search = Search::Thinking(
+                    Instant::now() + Duration::from_millis(response.delay),
+                    response,
+                );
+            }
+        } else if command == "stop" {
+            match std::mem::replace(&mut search, Search::Idle) {
+                Search::Thinking(_, response) | Search::Pondering(response) => answer(response),
+                _ => {}
+            }
+        } else if command == "quit" {
+            break;
+        }
+    }
+}
@@ -0,0 +1,1022 @@
+use log::{info, log_enabled, Level};
+
+use rand::prelude::*;
+
+use pgnparse::parser::*;
+
+use shakmaty::uci::Uci;
+use shakmaty::{Chess, Position};
+
+use tokio::io::AsyncWriteExt;
+
+use uciengine::analysis::Score;
+use uciengine::uciengine::*;
+
+use crate::game::*;
+use crate::learn::*;
+use crate::movesource::{engine_timeout, spawn_engine, MoveRequest};
+use crate::polyglot::*;
+
+/// lock serializing saves of learning books
+static LEARNING_BOOK_SAVE: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// book move selection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BookSelection {
+    /// move played most often, or with highest weight
+    Best,
+    /// random move, weighted by number of plays or weight
+    Weighted,
+    /// random move, weighted by performance, by weight if the book knows no results
+    WeightedByScore,
+    /// random move, all moves equally likely
+    Uniform,
+    /// random move, weighted by plays with the given percent chance, by performance otherwise
+    Mixed(usize),
+    /// random move, weighted by softmax of performance with the given temperature,
+    /// by share of the highest weight if the book knows no results, 0 temperature plays the best move
+    Softmax(f64),
+}
+
+/// candidate move of a book position
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookCandidate {
+    /// move in uci notation
+    pub uci: String,
+    /// number of plays, or polyglot weight
+    pub weight: usize,
+    /// performance for the side to move in percent, if the book knows results
+    pub perf: Option<usize>,
+}
+
+/// random candidate weighted by weight function, uniform if all weights are zero
+fn choose_weighted<'a, F, R>(
+    candidates: &'a [BookCandidate],
+    weight: F,
+    rng: &mut R,
+) -> Option<&'a BookCandidate>
+where
+    F: Fn(&BookCandidate) -> f64,
+    R: Rng + ?Sized,
+{
+    match candidates.iter().any(|candidate| weight(candidate) > 0.0) {
+        true => candidates.choose_weighted(rng, weight).ok(),
+        _ => candidates.choose(rng),
+    }
+}
+
+/// score of candidates between 0 and 1, performance if known for all candidates,
+/// share of the highest weight otherwise
+fn candidate_scores(candidates: &[BookCandidate]) -> Vec<f64> {
+    let max_weight = candidates
+        .iter()
+        .map(|candidate| candidate.weight)
+        .max()
+        .unwrap_or(0)
+        .max(1) as f64;
+
+    let perf_known = candidates.iter().all(|candidate| candidate.perf.is_some());
+
+    candidates
+        .iter()
+        .map(|candidate| match (perf_known, candidate.perf) {
+            (true, Some(perf)) => perf as f64 / 100.0,
+            _ => candidate.weight as f64 / max_weight,
+        })
+        .collect()
+}
+
+/// implementation of book selection
+impl BookSelection {
+    /// selection from name ( best, weighted, score, uniform, mixed, mixed:{percent}, softmax or
+    /// softmax:{temperature} ), None if unknown
+    pub fn from_name<T>(name: T) -> Option<BookSelection>
+    where
+        T: core::fmt::Display,
+    {
+        let name = name.to_string().to_lowercase();
+
+        match name.as_str() {
+            "best" => Some(BookSelection::Best),
+            "weighted" | "count" => Some(BookSelection::Weighted),
+            "score" => Some(BookSelection::WeightedByScore),
+            "uniform" => Some(BookSelection::Uniform),
+            "mixed" => Some(BookSelection::Mixed(90)),
+            "softmax" => Some(BookSelection::Softmax(0.1)),
+            _ => match name.split_once(':') {
+                Some(("mixed", percent)) => percent.parse().ok().map(BookSelection::Mixed),
+                Some(("softmax", temperature)) => temperature
+                    .parse()
+                    .ok()
+                    .filter(|temperature: &f64| *temperature >= 0.0)
+                    .map(BookSelection::Softmax),
+                _ => None,
+            },
+        }
+    }
+
+    /// select a candidate using rng, None if there are no candidates
+    pub fn select<'a, R>(
+        &self,
+        candidates: &'a [BookCandidate],
+        rng: &mut R,
+    ) -> Option<&'a BookCandidate>
+    where
+        R: Rng + ?Sized,
+    {
+        let by_weight = |candidate: &BookCandidate| candidate.weight as f64;
+        let by_perf = |candidate: &BookCandidate| candidate.perf.unwrap_or(0) as f64;
+        let perf_unknown = candidates.iter().all(|candidate| candidate.perf.is_none());
+
+        match self {
+            BookSelection::Best => candidates.iter().max_by_key(|candidate| candidate.weight),
+            BookSelection::Weighted => choose_weighted(candidates, by_weight, rng),
+            BookSelection::WeightedByScore => match perf_unknown {
+                true => choose_weighted(candidates, by_weight, rng),
+                _ => choose_weighted(candidates, by_perf, rng),
+            },
+            BookSelection::Uniform => candidates.choose(rng),
+            BookSelection::Mixed(percent) => {
+                match rng.gen_range(0..100) < *percent || perf_unknown {
+                    true => choose_weighted(candidates, by_weight, rng),
+                    _ => choose_weighted(candidates, by_perf, rng),
+                }
+            }
+            BookSelection::Softmax(temperature) => {
+                let scores = candidate_scores(candidates);
+
+                let max_score = scores.iter().cloned().fold(0.0, f64::max);
+
+                match *temperature > 0.0 {
+                    true => {
+                        let weights: Vec<f64> = scores
+                            .iter()
+                            .map(|score| ((score - max_score) / temperature).exp())
+                            .collect();
+
+                        rand::distributions::WeightedIndex::new(&weights)
+                            .ok()
+                            .map(|index| &candidates[index.sample(rng)])
+                    }
+                    _ => candidates
+                        .iter()
+                        .zip(scores.iter())
+                        .find(|(_, score)| **score >= max_score)
+                        .map(|(candidate, _)| candidate),
+                }
+            }
+        }
+    }
+}
+
+/// book move selection with a min performance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BookPolicy {
+    /// move selection
+    pub selection: BookSelection,
+    /// moves with a known performance below this percent are never played ( optional )
+    pub min_perf: Option<usize>,
+}
+
+/// implementation of book policy
+impl BookPolicy {
+    /// create policy with selection and no min performance
+    pub fn new(selection: BookSelection) -> BookPolicy {
+        BookPolicy {
+            selection,
+            min_perf: None,
+        }
+    }
+
+    /// set min performance and return self
+    pub fn min_perf(mut self, min_perf: Option<usize>) -> BookPolicy {
+        self.min_perf = min_perf;
+
+        self
+    }
+
+    /// select a candidate using rng, None if no candidate is good enough
+    pub fn select<R>(&self, candidates: &[BookCandidate], rng: &mut R) -> Option<BookCandidate>
+    where
+        R: Rng + ?Sized,
+    {
+        let candidates: Vec<BookCandidate> = candidates
+            .iter()
+            .filter(|candidate| match (candidate.perf, self.min_perf) {
+                (Some(perf), Some(min_perf)) => perf >= min_perf,
+                _ => true,
+            })
+            .cloned()
+            .collect();
+
+        self.selection.select(&candidates, rng).cloned()
+    }
+}
+
+/// default book policy, weighted without min performance
+impl Default for BookPolicy {
+    fn default() -> BookPolicy {
+        BookPolicy::new(BookSelection::Weighted)
+    }
+}
+
+/// opening book of any format
+#[derive(Debug, Clone)]
+pub enum OpeningBook {
+    /// book built from pgn, looked up by epd
+    Pgn(std::sync::Arc<Book>),
+    /// polyglot book, looked up by zobrist key
+    Polyglot(std::sync::Arc<PolyglotBook>),
+    /// book learned from the bot's own games, updated after every game
+    Learning(std::sync::Arc<std::sync::RwLock<LearningBook>>),
+}
+
+/// implementation of opening book
+impl OpeningBook {
+    /// load book file, polyglot if the file name ends with .bin, learning if it ends with .learn,
+    /// pgn otherwise, pgn books weight moves by the results of me,
+    /// a missing learning book is empty, other books fail if they are missing or empty
+    pub fn load<P, M>(path: P, me: M, max_ply: usize) -> Result<OpeningBook, String>
+    where
+        P: core::fmt::Display,
+        M: core::fmt::Display,
+    {
+        let path = path.to_string();
+
+        let extension = path
+            .to_lowercase()
+            .rsplit('.')
+            .next()
+            .map(|ext| ext.to_string());
+
+        match extension.as_deref() {
+            Some("bin") => {
+                let book = PolyglotBook::new()
+                    .load(&path)
+                    .map_err(|err| err.to_string())?;
+
+                match book.entries.is_empty() {
+                    true => Err("polyglot book has no entries".to_string()),
+                    _ => Ok(OpeningBook::Polyglot(std::sync::Arc::new(book))),
+                }
+            }
+            Some("learn") => {
+                let book = LearningBook::new()
+                    .load(&path)
+                    .map_err(|err| err.to_string())?;
+
+                Ok(OpeningBook::Learning(std::sync::Arc::new(
+                    std::sync::RwLock::new(book),
+                )))
+            }
+            _ => {
+                std::fs::File::open(&path).map_err(|err| err.to_string())?;
+
+                let mut book = Book::new().me(me).max_depth(max_ply);
+
+                book.parse(&path);
+
+                match book.positions.is_empty() {
+                    true => Err("pgn book has no positions".to_string()),
+                    _ => Ok(OpeningBook::Pgn(std::sync::Arc::new(book))),
+                }
+            }
+        }
+    }
+
+    /// candidate moves of position with epd, sorted by uci so a seeded selection is reproducible
+    pub fn candidates(&self, pos: &Chess, epd: &str) -> Vec<BookCandidate> {
+        let mut candidates = match self {
+            OpeningBook::Pgn(book) => match book.positions.get(epd) {
+                Some(pos) => pos
+                    .moves
+                    .values()
+                    .map(|m| BookCandidate {
+                        uci: m.uci.to_owned(),
+                        weight: m.plays(),
+                        perf: Some(m.perf()),
+                    })
+                    .collect(),
+                _ => vec![],
+            },
+            OpeningBook::Polyglot(book) => book
+                .moves(pos)
+                .into_iter()
+                .map(|(uci, weight)| BookCandidate {
+                    uci,
+                    weight: weight as usize,
+                    perf: None,
+                })
+                .collect(),
+            OpeningBook::Learning(book) => match book.read() {
+                Ok(book) => book.candidates(epd),
+                _ => vec![],
+            },
+        };
+
+        candidates.sort_by(|a, b| a.uci.cmp(&b.uci));
+
+        candidates
+    }
+}
+
+/// modification time of file, None if it cannot be read
+fn file_modified<T>(path: T) -> Option<std::time::SystemTime>
+where
+    T: AsRef<std::path::Path>,
+{
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+/// games a book is used in, empty lists match everything
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BookScope {
+    /// speeds ( ultraBullet, bullet, blitz, rapid, classical, correspondence )
+    pub speeds: Vec<String>,
+    /// variant keys
+    pub variants: Vec<String>,
+    /// colors of the bot ( white, black )
+    pub colors: Vec<String>,
+    /// rated games only if true, casual games only if false
+    pub rated: Option<bool>,
+}
+
+/// comma separated list to lower case names
+fn name_list<T>(list: T) -> Vec<String>
+where
+    T: core::fmt::Display,
+{
+    list.to_string()
+        .split(',')
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// implementation of book scope
+impl BookScope {
+    /// true if scope includes game
+    pub fn matches(&self, game: &GameInfo) -> bool {
+        let color = match game.bot_white {
+            true => "white",
+            _ => "black",
+        };
+
+        let includes =
+            |list: &Vec<String>, name: &str| list.is_empty() || list.contains(&name.to_lowercase());
+
+        includes(&self.speeds, &game.speed)
+            && includes(&self.variants, &game.variant)
+            && includes(&self.colors, color)
+            && self.rated.map(|rated| rated == game.rated).unwrap_or(true)
+    }
+}
+
+/// opening book with scope, depth limit and selection
+#[derive(Debug, Clone)]
+pub struct ScopedBook {
+    /// name, used in logs
+    pub name: String,
+    /// book
+    pub book: OpeningBook,
+    /// games the book is used in
+    pub scope: BookScope,
+    /// max number of moves played for using the book
+    pub max_ply: usize,
+    /// move selection policy
+    pub policy: BookPolicy,
+    /// move selection policies by speed, overriding policy
+    pub speed_policies: std::collections::HashMap<String, BookPolicy>,
+    /// book file, if loaded from a file
+    pub path: Option<String>,
+    /// modification time of the book file when loaded
+    pub modified: Option<std::time::SystemTime>,
+}
+
+/// implementation of scoped book
+impl ScopedBook {
+    /// create scoped book used in all games
+    pub fn new<T>(name: T, book: OpeningBook) -> ScopedBook
+    where
+        T: core::fmt::Display,
+    {
+        ScopedBook {
+            name: name.to_string(),
+            book,
+            scope: BookScope::default(),
+            max_ply: 20,
+            policy: BookPolicy::default(),
+            speed_policies: std::collections::HashMap::new(),
+            path: None,
+            modified: None,
+        }
+    }
+
+    /// set speeds as comma separated list and return self
+    pub fn speeds<T>(mut self, speeds: T) -> ScopedBook
+    where
+        T: core::fmt::Display,
+    {
+        self.scope.speeds = name_list(speeds);
+
+        self
+    }
+
+    /// set variants as comma separated list and return self
+    pub fn variants<T>(mut self, variants: T) -> ScopedBook
+    where
+        T: core::fmt::Display,
+    {
+        self.scope.variants = name_list(variants);
+
+        self
+    }
+
+    /// set colors as comma separated list and return self
+    pub fn colors<T>(mut self, colors: T) -> ScopedBook
+    where
+        T: core::fmt::Display,
+    {
+        self.scope.colors = name_list(colors);
+
+        self
+    }
+
+    /// restrict to rated or casual games and return self
+    pub fn rated(mut self, rated: Option<bool>) -> ScopedBook {
+        self.scope.rated = rated;
+
+        self
+    }
+
+    /// set max ply and return self
+    pub fn max_ply(mut self, max_ply: usize) -> ScopedBook {
+        self.max_ply = max_ply;
+
+        self
+    }
+
+    /// set selection and return self
+    pub fn selection(mut self, selection: BookSelection) -> ScopedBook {
+        self.policy.selection = selection;
+
+        self
+    }
+
+    /// set min performance and return self
+    pub fn min_perf(mut self, min_perf: Option<usize>) -> ScopedBook {
+        self.policy.min_perf = min_perf;
+
+        self
+    }
+
+    /// set policy for speed and return self
+    pub fn speed_policy<T>(mut self, speed: T, policy: BookPolicy) -> ScopedBook
+    where
+        T: core::fmt::Display,
+    {
+        self.speed_policies
+            .insert(speed.to_string().to_lowercase(), policy);
+
+        self
+    }
+
+    /// policy used for speed
+    pub fn policy_for<T>(&self, speed: T) -> BookPolicy
+    where
+        T: core::fmt::Display,
+    {
+        self.speed_policies
+            .get(&speed.to_string().to_lowercase())
+            .cloned()
+            .unwrap_or(self.policy)
+    }
+
+    /// load book file, see OpeningBook::load, None if the book cannot be loaded
+    pub fn load<P, M>(path: P, me: M, max_ply: usize) -> Option<ScopedBook>
+    where
+        P: core::fmt::Display,
+        M: core::fmt::Display,
+    {
+        let path = path.to_string();
+
+        match OpeningBook::load(&path, me, max_ply) {
+            Ok(book) => {
+                let mut book = ScopedBook::new(&path, book).max_ply(max_ply);
+
+                book.modified = file_modified(&path);
+                book.path = Some(path);
+
+                Some(book)
+            }
+            Err(err) => {
+                if log_enabled!(Level::Info) {
+                    info!("could not load book {} : {}", path, err);
+                }
+
+                None
+            }
+        }
+    }
+
+    /// true if the book file was modified since the book was loaded, learning books never change
+    pub fn changed(&self) -> bool {
+        match (&self.book, &self.path) {
+            (OpeningBook::Learning(_), _) | (_, None) => false,
+            (_, Some(path)) => file_modified(path) != self.modified,
+        }
+    }
+
+    /// book with the same settings loaded again from the book file, learning books are kept
+    pub fn reload(&self) -> Result<ScopedBook, String> {
+        let path = match (&self.book, &self.path) {
+            (OpeningBook::Learning(_), _) => return Ok(self.clone()),
+            (_, Some(path)) => path,
+            _ => return Err(format!("book {} has no file", self.name)),
+        };
+
+        let me = match &self.book {
+            OpeningBook::Pgn(book) => book.me.to_owned().unwrap_or_default(),
+            _ => String::new(),
+        };
+
+        let modified = file_modified(path);
+
+        let book = OpeningBook::load(path, me, self.max_ply)?;
+
+        Ok(ScopedBook {
+            book,
+            modified,
+            ..self.clone()
+        })
+    }
+
+    /// book from spec, a book file followed by space separated key=value settings
+    ///
+    /// keys are speeds, variants, colors ( comma separated lists ), rated ( true or false ),
+    /// depth ( max ply ), selection ( see BookSelection::from_name ) and min_score ( min performance
+    /// in percent ), selection.{speed} and min_score.{speed} set them for one speed only,
+    /// learning books also take decay and min_games
+    ///
+    /// example `repertoire.pgn speeds=blitz,bullet colors=white rated=true depth=12 selection=best`
+    pub fn from_spec<S, M>(spec: S, me: M, default_max_ply: usize) -> Option<ScopedBook>
+    where
+        S: core::fmt::Display,
+        M: core::fmt::Display,
+    {
+        let spec = spec.to_string();
+
+        let mut parts = spec.split_whitespace();
+
+        let path = parts.next()?;
+
+        let mut settings: Vec<(&str, &str)> = parts
+            .filter_map(|part| {
+                let mut key_value = part.splitn(2, '=');
+
+                Some((key_value.next()?, key_value.next()?))
+            })
+            .collect();
+
+        // speed policies start from the book policy, so they are set last
+        settings.sort_by_key(|(key, _)| key.contains('.'));
+
+        let max_ply = settings
+            .iter()
+            .find(|(key, _)| *key == "depth")
+            .and_then(|(_, value)| value.parse().ok())
+            .unwrap_or(default_max_ply);
+
+        let mut book = ScopedBook::load(path, me, max_ply)?;
+
+        book.policy.selection = match book.book {
+            OpeningBook::Pgn(_) => BookSelection::Mixed(90),
+            OpeningBook::Learning(_) => BookSelection::Mixed(0),
+            _ => book.policy.selection,
+        };
+
+        for (key, value) in settings {
+            if let Some((key, speed)) = key.split_once('.') {
+                let mut policy = book.policy_for(speed);
+
+                match (key, BookSelection::from_name(value)) {
+                    ("selection", Some(selection)) => policy.selection = selection,
+                    ("min_score", _) => policy.min_perf = value.parse().ok(),
+                    _ => {
+                        if log_enabled!(Level::Info) {
+                            info!("unknown book setting {}.{} in {}", key, speed, spec);
+                        }
+                    }
+                }
+
+                book = book.speed_policy(speed, policy);
+
+                continue;
+            }
+
+            book = match key {
+                "speeds" => book.speeds(value),
+                "variants" => book.variants(value),
+                "colors" => book.colors(value),
+                "rated" => book.rated(value.parse().ok()),
+                "selection" => match BookSelection::from_name(value) {
+                    Some(selection) => book.selection(selection),
+                    _ => book,
+                },
+                "min_score" => book.min_perf(value.parse().ok()),
+                "depth" => book,
+                "decay" | "min_games" => {
+                    if let OpeningBook::Learning(learning) = &book.book {
+                        if let Ok(mut learning) = learning.write() {
+                            match key {
+                                "decay" => {
+                                    if let Ok(decay) = value.parse::<f64>() {
+                                        learning.decay = decay.clamp(0.0, 1.0);
+                                    }
+                                }
+                                _ => {
+                                    if let Ok(min_games) = value.parse() {
+                                        learning.min_games = min_games;
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    book
+                }
+                _ => {
+                    if log_enabled!(Level::Info) {
+                        info!("unknown book setting {} in {}", key, spec);
+                    }
+
+                    book
+                }
+            };
+        }
+
+        Some(book)
+    }
+
+    /// learn game result from the bot's point of view, if this is a learning book used in the game,
+    /// and save the book, blocking on file io
+    pub fn learn<T>(&self, game: &GameInfo, moves: T, result: f64)
+    where
+        T: core::fmt::Display,
+    {
+        let learning = match &self.book {
+            OpeningBook::Learning(learning) => learning,
+            _ => return,
+        };
+
+        if !self.scope.matches(game) || (game.initial_fen != "startpos") {
+            return;
+        }
+
+        if let Ok(mut learning) = learning.write() {
+            learning.learn(moves, game.bot_white, result, self.max_ply);
+        }
+
+        // saves are serialized, each taking the latest content, while the file is written
+        // the book stays unlocked for games looking up moves
+        let _saving = LEARNING_BOOK_SAVE.lock();
+
+        let file = match learning.read() {
+            Ok(learning) => learning
+                .path
+                .to_owned()
+                .map(|path| (path, learning.content())),
+            _ => None,
+        };
+
+        if let Some((path, content)) = file {
+            if let Err(err) = write_learning_book(path, content) {
+                if log_enabled!(Level::Info) {
+                    info!("could not save learning book {} : {:?}", self.name, err);
+                }
+            }
+        }
+    }
+
+    /// select book move in uci notation using rng, with the policy for the speed of the game,
+    /// None if the book is out of scope, too deep or has no good enough move
+    pub fn get_move<R>(&self, req: &MoveRequest, rng: &mut R) -> Option<String>
+    where
+        R: Rng + ?Sized,
+    {
+        if !self.scope.matches(&req.game) || (req.ply() >= self.max_ply) {
+            return None;
+        }
+
+        let candidates = self.book.candidates(&req.pos, &req.epd);
+
+        self.policy_for(&req.game.speed)
+            .select(&candidates, rng)
+            .map(|candidate| candidate.uci)
+    }
+}
+
+/// books in order of priority, shared by the bot and its games,
+/// reloading replaces the whole list at once, so a move is always chosen from one version of the books
+#[derive(Debug, Clone, Default)]
+pub struct BookList {
+    /// books
+    pub books: std::sync::Arc<std::sync::RwLock<Vec<ScopedBook>>>,
+}
+
+/// implementation of book list
+impl BookList {
+    /// create empty book list
+    pub fn new() -> BookList {
+        BookList::default()
+    }
+
+    /// current books
+    pub fn snapshot(&self) -> Vec<ScopedBook> {
+        match self.books.read() {
+            Ok(books) => books.clone(),
+            _ => vec![],
+        }
+    }
+
+    /// replace books
+    pub fn set(&self, books: Vec<ScopedBook>) {
+        if let Ok(mut current) = self.books.write() {
+            *current = books;
+        }
+    }
+
+    /// add book with lowest priority
+    pub fn push(&self, book: ScopedBook) {
+        if let Ok(mut books) = self.books.write() {
+            books.push(book);
+        }
+    }
+
+    /// reload books from their files, all of them or only changed ones,
+    /// a book that fails to load is kept as it was, returns the errors
+    pub fn reload(&self, only_changed: bool) -> Vec<String> {
+        let mut errors = vec![];
+
+        let mut reloaded = false;
+
+        let books: Vec<ScopedBook> = self
+            .snapshot()
+            .into_iter()
+            .map(|book| {
+                if only_changed && !book.changed() {
+                    return book;
+                }
+
+                match book.reload() {
+                    Ok(new_book) => {
+                        if log_enabled!(Level::Info) {
+                            info!("book {} reloaded", book.name);
+                        }
+
+                        reloaded = true;
+
+                        new_book
+                    }
+                    Err(err) => {
+                        errors.push(format!("could not reload book {} : {}", book.name, err));
+
+                        // a failed version of the file is not retried, only the next change of it
+                        ScopedBook {
+                            modified: file_modified(book.path.as_deref().unwrap_or_default()),
+                            ..book
+                        }
+                    }
+                }
+            })
+            .collect();
+
+        if reloaded || !errors.is_empty() {
+            self.set(books);
+        }
+
+        for err in &errors {
+            if log_enabled!(Level::Info) {
+                info!("{}", err);
+            }
+        }
+
+        errors
+    }
+
+    /// reload books on a blocking thread, see reload
+    pub async fn reload_in_background(&self, only_changed: bool) -> Vec<String> {
+        let list = self.clone();
+
+        tokio::task::spawn_blocking(move || list.reload(only_changed))
+            .await
+            .unwrap_or_else(|err| vec![err.to_string()])
+    }
+
+    /// poll book files every interval and reload changed books
+    pub async fn watch(self, interval: std::time::Duration) {
+        loop {
+            tokio::time::sleep(interval).await;
+
+            self.reload_in_background(true).await;
+        }
+    }
+}
+
+/// min time left in milliseconds for verifying book moves, with less time book moves are played unverified
+pub const BOOK_VERIFY_MIN_TIME: usize = 10000;
+
+/// score in centipawns, mates are scored beyond any centipawn score, shorter mates higher
+pub fn score_cp(score: &Score) -> i32 {
+    match score {
+        Score::Cp(cp) => *cp,
+        Score::Mate(moves) if *moves > 0 => 100000 - moves,
+        Score::Mate(moves) => -100000 - moves,
+    }
+}
+
+/// checks book moves with a fixed depth engine search,
+/// the engine is started for the first check of a game and quit at the end of the game
+pub struct BookVerifier {
+    /// engine executable name
+    pub engine_name: String,
+    /// uci options
+    pub uci_options: std::collections::HashMap<String, String>,
+    /// search depth
+    pub depth: usize,
+    /// max eval drop of a book move below the best move in centipawns
+    pub margin: i32,
+    /// file rejected book moves are appended to ( optional )
+    pub log_file: Option<String>,
+    /// engine of current game
+    pub engine: Option<std::sync::Arc<UciEngine>>,
+}
+
+/// implementation of book verifier
+impl BookVerifier {
+    /// create book verifier
+    pub fn new<T>(
+        engine_name: T,
+        uci_options: std::collections::HashMap<String, String>,
+        depth: usize,
+        margin: i32,
+    ) -> BookVerifier
+    where
+        T: core::fmt::Display,
+    {
+        BookVerifier {
+            engine_name: engine_name.to_string(),
+            uci_options,
+            depth,
+            margin,
+            log_file: None,
+            engine: None,
+        }
+    }
+
+    /// set log file and return self
+    pub fn log_file(mut self, log_file: Option<String>) -> BookVerifier {
+        self.log_file = log_file;
+
+        self
+    }
+
+    /// score of fen after moves from the point of view of the side to move, None if the engine fails
+    async fn search(&mut self, fen: &str, moves: &str) -> Option<i32> {
+        let started = self.engine.is_none();
+
+        if started {
+            self.engine = spawn_engine(&self.engine_name);
+        }
+
+        let engine = self.engine.to_owned()?;
+
+        let mut go_job = GoJob::new().pos_fen(fen).go_opt("depth", self.depth);
+
+        if !moves.is_empty() {
+            go_job = go_job.pos_moves(moves);
+        }
+
+        // uci options are set once, when the engine is started
+        if started {
+            for (key, value) in &self.uci_options {
+                go_job = go_job.uci_opt(key, value);
+            }
+        }
+
+        match engine.go(go_job).await {
+            Ok(go_result) => Some(score_cp(&go_result.ai.score)),
+            _ => {
+                // a crashed engine is started again for the next check
+                self.engine = None;
+
+                None
+            }
+        }
+    }
+
+    /// eval drop of book move below the best move in centipawns, None if it cannot be checked
+    pub async fn eval_drop(&mut self, req: &MoveRequest, uci: &str) -> Option<i32> {
+        let m = uci.parse::<Uci>().ok()?.to_move(&req.pos).ok()?;
+
+        let mut after = req.pos.clone();
+
+        after.play_unchecked(&m);
+
+        // mate or stalemate needs no search, it ends the game
+        if after.legals().is_empty() {
+            return None;
+        }
+
+        let best = self.search(&req.fen, "").await?;
+
+        let book = -self.search(&req.fen, uci).await?;
+
+        Some(best - book)
+    }
+
+    /// true if book move may be played, rejections are logged, a move the engine
+    /// cannot check in time is rejected
+    pub async fn verify(&mut self, req: &MoveRequest, book_name: &str, uci: &str) -> bool {
+        if req.bot_time() < BOOK_VERIFY_MIN_TIME {
+            return true;
+        }
+
+        let timeout = engine_timeout(req);
+
+        let drop = match tokio::time::timeout(
+            std::time::Duration::from_millis(timeout as u64),
+            self.eval_drop(req, uci),
+        )
+        .await
+        {
+            Ok(Some(drop)) => drop,
+            Ok(None) => return true,
+            _ => {
+                // the search of a hung engine stays queued, it is quit and started again
+                // for the next check
+                if let Some(engine) = self.engine.take() {
+                    engine.quit();
+                }
+
+                if log_enabled!(Level::Info) {
+                    info!(
+                        "book move {} of {} rejected in {} , engine did not answer within {} ms",
+                        uci, book_name, req.epd, timeout
+                    );
+                }
+
+                return false;
+            }
+        };
+
+        if drop <= self.margin {
+            return true;
+        }
+
+        if log_enabled!(Level::Info) {
+            info!(
+                "book move {} of {} rejected in {} , eval drop {} cp",
+                uci, book_name, req.epd, drop
+            );
+        }
+
+        if let Some(log_file) = &self.log_file {
+            let line = format!("{}\t{}\t{}\t{}\n", req.epd, uci, book_name, drop);
+
+            let result = match tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(log_file)
+                .await
+            {
+                // a tokio file finishes writing in the background, unless flushed
+                Ok(mut file) => match file.write_all(line.as_bytes()).await {
+                    Ok(_) => file.flush().await,
+                    Err(err) => Err(err),
+                },
+                Err(err) => Err(err),
+            };
+
+            if let Err(err) = result {
+                if log_enabled!(Level::Info) {
+                    info!(
+                        "could not log rejected book move to {} : {:?}",
+                        log_file, err
+                    );
+                }
+            }
+        }
+
+        false
+    }
+
+    /// quit engine at the end of a game
+    pub fn end_game(&mut self) {
+        if let Some(engine) = self.engine.take() {
+            engine.quit();
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use log::{debug, log_enabled, Level};
+
+use licoricedev::errors::LichessError;
+use licoricedev::models::board::BoardState;
+
+/// lichess base url
+pub const LICHESS_URL: &str = "https://lichess.org";
+
+/// remove flag from json object, false if it is missing
+fn take_flag(object: &mut serde_json::Map<String, serde_json::Value>, key: &str) -> bool {
+    object
+        .remove(key)
+        .and_then(|flag| flag.as_bool())
+        .unwrap_or(false)
+}
+
+/// event of a bot game stream, with the takeback proposals the lichess client does not know of
+#[derive(Debug, Clone)]
+pub struct GameEvent {
+    /// event as modelled by the lichess client
+    pub event: BoardState,
+    /// white proposes a takeback
+    pub wtakeback: bool,
+    /// black proposes a takeback
+    pub btakeback: bool,
+}
+
+/// implementation of game event
+impl GameEvent {
+    /// parse line of a game stream, the takeback proposals are taken out of the game state
+    /// first, as the lichess client model rejects unknown fields
+    pub fn parse<T>(line: T) -> Result<GameEvent, LichessError>
+    where
+        T: core::fmt::Display,
+    {
+        let mut value: serde_json::Value = serde_json::from_str(&line.to_string())?;
+
+        let game_full = value.get("type").and_then(|kind| kind.as_str()) == Some("gameFull");
+
+        let state = match game_full {
+            true => value.get_mut("state"),
+            _ => Some(&mut value),
+        };
+
+        let (wtakeback, btakeback) = match state {
+            Some(serde_json::Value::Object(state)) => {
+                (take_flag(state, "wtakeback"), take_flag(state, "btakeback"))
+            }
+            _ => (false, false),
+        };
+
+        Ok(GameEvent {
+            event: serde_json::from_value(value)?,
+            wtakeback,
+            btakeback,
+        })
+    }
+}
+
+/// events of a bot game, read line by line
+pub struct GameStream {
+    /// streamed response
+    response: reqwest::Response,
+    /// bytes received after the last complete line
+    buffer: Vec<u8>,
+}
+
+/// implementation of game stream
+impl GameStream {
+    /// next event, None at the end of the stream, keep alive lines are skipped,
+    /// no event is lost if the future is dropped while waiting
+    pub async fn next_event(&mut self) -> Result<Option<GameEvent>, LichessError> {
+        loop {
+            if let Some(index) = self.buffer.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=index).collect();
+
+                let line = String::from_utf8_lossy(&line).trim().to_string();
+
+                // empty lines keep the connection alive
+                if !line.is_empty() {
+                    if log_enabled!(Level::Debug) {
+                        debug!("game stream line {}", line);
+                    }
+
+                    return GameEvent::parse(line).map(Some);
+                }
+
+                continue;
+            }
+
+            match self.response.chunk().await? {
+                Some(chunk) => self.buffer.extend_from_slice(&chunk),
+                _ => {
+                    // the last line may have no line break
+                    let line = String::from_utf8_lossy(&std::mem::take(&mut self.buffer))
+                        .trim()
+                        .to_string();
+
+                    return match line.is_empty() {
+                        true => Ok(None),
+                        _ => GameEvent::parse(line).map(Some),
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// requests of the lichess bot api the lichess client lacks
+pub struct BotApi {
+    /// http client sending the token
+    pub client: reqwest::Client,
+    /// base url
+    pub base: String,
+}
+
+/// implementation of bot api
+impl BotApi {
+    /// create bot api client authenticated with token
+    pub fn new<T>(token: T) -> BotApi
+    where
+        T: core::fmt::Display,
+    {
+        let mut headers = reqwest::header::HeaderMap::new();
+
+        if let Ok(mut value) = reqwest::header::HeaderValue::from_str(&format!("Bearer {}", token))
+        {
+            value.set_sensitive(true);
+
+            headers.insert(reqwest::header::AUTHORIZATION, value);
+        }
+
+        BotApi {
+            client: reqwest::Client::builder()
+                .default_headers(headers)
+                .build()
+                .unwrap_or_default(),
+            base: LICHESS_URL.to_string(),
+        }
+    }
+
+    /// set base url and return self
+    pub fn base<T>(mut self, base: T) -> BotApi
+    where
+        T: core::fmt::Display,
+    {
+        self.base = base.to_string();
+
+        self
+    }
+
+    /// send request, Err if lichess does not answer with success
+    async fn send(request: reqwest::RequestBuilder) -> Result<reqwest::Response, LichessError> {
+        let response = request.send().await?;
+
+        match response.status() {
+            status if status.is_success() => Ok(response),
+            reqwest::StatusCode::TOO_MANY_REQUESTS => Err(LichessError::RateLimited(
+                response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|retry_after| retry_after.to_str().ok())
+                    .and_then(|retry_after| retry_after.parse().ok()),
+            )),
+            status => Err(status.into()),
+        }
+    }
+
+    /// stream events of bot game
+    pub async fn stream_game<T>(&self, game_id: T) -> Result<GameStream, LichessError>
+    where
+        T: core::fmt::Display,
+    {
+        let url = format!("{}/api/bot/game/stream/{}", self.base, game_id);
+
+        Ok(GameStream {
+            response: BotApi::send(self.client.get(&url)).await?,
+            buffer: vec![],
+        })
+    }
+
+    /// accept or decline the takeback proposal of the opponent in bot game
+    pub async fn handle_takeback<T>(&self, game_id: T, accept: bool) -> Result<(), LichessError>
+    where
+        T: core::fmt::Display,
+    {
+        let url = format!(
+            "{}/api/bot/game/{}/takeback/{}",
+            self.base,
+            game_id,
+            match accept {
+                true => "yes",
+                _ => "no",
+            }
+        );
+
+        BotApi::send(self.client.post(&url)).await?;
+
+        Ok(())
+    }
+}
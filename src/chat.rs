@@ -0,0 +1,300 @@
+use uciengine::analysis::Score;
+
+use crate::game::*;
+use crate::movesource::MoveProposal;
+
+/// chat commands answered by the bot
+pub const CHAT_COMMANDS: [&str; 4] = ["!eval", "!engine", "!book", "!help"];
+
+/// chat settings of the bot
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatConfig {
+    /// message posted when a game starts, see render for variables ( optional )
+    pub greeting: Option<String>,
+    /// message posted when a game is over, see render for variables ( optional )
+    pub goodbye: Option<String>,
+    /// rooms greetings and goodbyes are posted in ( player, spectator )
+    pub rooms: Vec<String>,
+    /// answer chat commands
+    pub commands: bool,
+    /// min time between answers in a game, in milliseconds
+    pub min_interval: u64,
+    /// max number of answers in a game
+    pub max_answers: usize,
+}
+
+/// implementation of chat config
+impl ChatConfig {
+    /// create chat config without greeting and goodbye, answering commands
+    pub fn new() -> ChatConfig {
+        ChatConfig {
+            greeting: None,
+            goodbye: None,
+            rooms: vec!["player".to_string(), "spectator".to_string()],
+            commands: true,
+            min_interval: 5000,
+            max_answers: 20,
+        }
+    }
+
+    /// set greeting and return self
+    pub fn greeting<T>(mut self, greeting: T) -> ChatConfig
+    where
+        T: core::fmt::Display,
+    {
+        self.greeting = Some(greeting.to_string()).filter(|greeting| !greeting.is_empty());
+
+        self
+    }
+
+    /// set goodbye and return self
+    pub fn goodbye<T>(mut self, goodbye: T) -> ChatConfig
+    where
+        T: core::fmt::Display,
+    {
+        self.goodbye = Some(goodbye.to_string()).filter(|goodbye| !goodbye.is_empty());
+
+        self
+    }
+
+    /// set rooms as comma separated list and return self
+    pub fn rooms<T>(mut self, rooms: T) -> ChatConfig
+    where
+        T: core::fmt::Display,
+    {
+        self.rooms = rooms
+            .to_string()
+            .split(',')
+            .map(|room| room.trim().to_lowercase())
+            .filter(|room| room == "player" || room == "spectator")
+            .collect();
+
+        self
+    }
+
+    /// enable or disable commands and return self
+    pub fn commands(mut self, commands: bool) -> ChatConfig {
+        self.commands = commands;
+
+        self
+    }
+
+    /// set rate limit of answers and return self
+    pub fn rate_limit(mut self, min_interval: u64, max_answers: usize) -> ChatConfig {
+        self.min_interval = min_interval;
+        self.max_answers = max_answers;
+
+        self
+    }
+}
+
+/// default chat config
+impl Default for ChatConfig {
+    fn default() -> ChatConfig {
+        ChatConfig::new()
+    }
+}
+
+/// template with {name} variables replaced by their values, unknown variables are kept
+pub fn render<T>(template: T, vars: &[(&str, String)]) -> String
+where
+    T: core::fmt::Display,
+{
+    vars.iter()
+        .fold(template.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), value)
+        })
+}
+
+/// score from the bot's point of view in pawns, or as mate
+pub fn score_text(score: &Score) -> String {
+    match score {
+        Score::Cp(cp) => format!("{:+.2}", *cp as f64 / 100.0),
+        Score::Mate(n) if *n > 0 => format!("mate in {}", n),
+        Score::Mate(n) => format!("mated in {}", -n),
+    }
+}
+
+/// result of game record in pgn notation, * if the game is not over
+pub fn result_text(record: &GameRecord) -> String {
+    record
+        .outcome()
+        .map(|outcome| outcome.pgn_result())
+        .unwrap_or_else(|| "*".to_string())
+}
+
+/// chat of a game
+#[derive(Debug, Clone)]
+pub struct GameChat {
+    /// settings
+    pub config: ChatConfig,
+    /// bot name
+    pub bot_name: String,
+    /// engine name, or the name of the built in search
+    pub engine_name: String,
+    /// names of books used in the game
+    pub books: Vec<String>,
+    /// last move played by the bot
+    pub last_move: Option<MoveProposal>,
+    /// number of answers
+    answers: usize,
+    /// time of last answer
+    last_answer: Option<std::time::Instant>,
+}
+
+/// implementation of game chat
+impl GameChat {
+    /// create game chat
+    pub fn new<B, E>(config: ChatConfig, bot_name: B, engine_name: E) -> GameChat
+    where
+        B: core::fmt::Display,
+        E: core::fmt::Display,
+    {
+        GameChat {
+            config,
+            bot_name: bot_name.to_string(),
+            engine_name: engine_name.to_string(),
+            books: vec![],
+            last_move: None,
+            answers: 0,
+            last_answer: None,
+        }
+    }
+
+    /// set names of books used in the game and return self
+    pub fn books(mut self, books: Vec<String>) -> GameChat {
+        self.books = books;
+
+        self
+    }
+
+    /// variables of templates, opponent, opponent_rating, bot, engine, speed and result
+    pub fn vars(&self, record: &GameRecord) -> Vec<(&'static str, String)> {
+        let opponent = record.info.opponent();
+
+        vec![
+            ("opponent", opponent.name.to_owned()),
+            (
+                "opponent_rating",
+                opponent
+                    .rating
+                    .map(|rating| rating.to_string())
+                    .unwrap_or_else(|| "?".to_string()),
+            ),
+            ("bot", self.bot_name.to_owned()),
+            ("engine", self.engine_name.to_owned()),
+            ("speed", record.info.speed.to_owned()),
+            ("result", result_text(record)),
+        ]
+    }
+
+    /// messages of template in all rooms
+    fn post(&self, template: &Option<String>, record: &GameRecord) -> Vec<(String, String)> {
+        match template {
+            Some(template) => {
+                let text = render(template, &self.vars(record));
+
+                self.config
+                    .rooms
+                    .iter()
+                    .map(|room| (room.to_owned(), text.to_owned()))
+                    .collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// greeting messages as room and text
+    pub fn greeting(&self, record: &GameRecord) -> Vec<(String, String)> {
+        self.post(&self.config.greeting, record)
+    }
+
+    /// goodbye messages as room and text
+    pub fn goodbye(&self, record: &GameRecord) -> Vec<(String, String)> {
+        self.post(&self.config.goodbye, record)
+    }
+
+    /// remember move played by the bot, for answering commands
+    pub fn move_played(&mut self, proposal: &MoveProposal) {
+        self.last_move = Some(proposal.clone());
+    }
+
+    /// answer of command asked in room, the eval is not given to the opponent in the player
+    /// room, as it would be engine help during the game
+    fn command_answer(&self, command: &str, room: &str) -> Option<String> {
+        match command {
+            "!eval" if room == "player" => Some("no eval in the player room".to_string()),
+            "!help" => Some(format!("commands : {}", CHAT_COMMANDS.join(" "))),
+            "!engine" => Some(format!("playing with {}", self.engine_name)),
+            "!eval" => Some(
+                match self
+                    .last_move
+                    .as_ref()
+                    .and_then(|m| m.score.as_ref().map(|score| (m, score)))
+                {
+                    Some((m, score)) => match m.depth {
+                        Some(depth) => format!("eval {} depth {}", score_text(score), depth),
+                        _ => format!("eval {}", score_text(score)),
+                    },
+                    _ => "no eval yet".to_string(),
+                },
+            ),
+            "!book" => {
+                let books = match self.books.is_empty() {
+                    true => "no books".to_string(),
+                    _ => format!("books {}", self.books.join(", ")),
+                };
+
+                Some(match &self.last_move {
+                    Some(m) if m.source == "book" || m.source == "repertoire" => {
+                        format!("{} , last move {} from {}", books, m.uci, m.source)
+                    }
+                    Some(_) => format!("{} , out of book", books),
+                    _ => books,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// answer to chat line as room and text, None if the line is no command, the bot wrote it
+    /// or the rate limit is reached
+    pub fn answer<U, T, R>(&mut self, username: U, text: T, room: R) -> Option<(String, String)>
+    where
+        U: core::fmt::Display,
+        T: core::fmt::Display,
+        R: core::fmt::Display,
+    {
+        let username = username.to_string().to_lowercase();
+
+        if !self.config.commands
+            || (username == self.bot_name.to_lowercase())
+            || (username == "lichess")
+        {
+            return None;
+        }
+
+        let text = text.to_string();
+
+        let command = text.split_whitespace().next()?.to_lowercase();
+
+        let room = room.to_string();
+
+        let answer = self.command_answer(&command, &room)?;
+
+        if self.answers >= self.config.max_answers {
+            return None;
+        }
+
+        if let Some(last_answer) = self.last_answer {
+            if last_answer.elapsed() < std::time::Duration::from_millis(self.config.min_interval) {
+                return None;
+            }
+        }
+
+        self.answers += 1;
+        self.last_answer = Some(std::time::Instant::now());
+
+        Some((room, answer))
+    }
+}
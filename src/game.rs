@@ -0,0 +1,486 @@
+use log::{info, log_enabled, Level};
+
+use licoricedev::models::board::Challengee::{LightUser, StockFish};
+use licoricedev::models::board::{Challengee, GameFull, GameState};
+
+use uciengine::analysis::Score;
+use uciengine::uciengine::Timecontrol;
+
+/// clock of a game, all values are in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Clock {
+    /// white time
+    pub wtime: usize,
+    /// white increment
+    pub winc: usize,
+    /// black time
+    pub btime: usize,
+    /// black increment
+    pub binc: usize,
+}
+
+/// clock implementation
+impl Clock {
+    /// clock of game state
+    pub fn from_state(state: &GameState) -> Clock {
+        Clock {
+            wtime: state.wtime as usize,
+            winc: state.winc as usize,
+            btime: state.btime as usize,
+            binc: state.binc as usize,
+        }
+    }
+
+    /// remaining time of side
+    pub fn time(&self, white: bool) -> usize {
+        match white {
+            true => self.wtime,
+            _ => self.btime,
+        }
+    }
+
+    /// increment of side
+    pub fn inc(&self, white: bool) -> usize {
+        match white {
+            true => self.winc,
+            _ => self.binc,
+        }
+    }
+
+    /// clock after side spent elapsed milliseconds, never less than 100 ms is left
+    pub fn spend(mut self, white: bool, elapsed: usize) -> Clock {
+        let time = match self.time(white) > elapsed {
+            true => self.time(white) - elapsed,
+            _ => 0,
+        }
+        .max(100);
+
+        match white {
+            true => self.wtime = time,
+            _ => self.btime = time,
+        }
+
+        self
+    }
+
+    /// uci engine time control
+    pub fn timecontrol(&self) -> Timecontrol {
+        Timecontrol {
+            wtime: self.wtime,
+            winc: self.winc,
+            btime: self.btime,
+            binc: self.binc,
+        }
+    }
+}
+
+/// player of a game
+#[derive(Debug, Clone, PartialEq)]
+pub struct Player {
+    /// lichess username, or Stockfish AI level
+    pub name: String,
+    /// rating
+    pub rating: Option<u16>,
+    /// title ( BOT for bots )
+    pub title: Option<String>,
+    /// Stockfish AI level, if playing against lichess AI
+    pub ai_level: Option<u8>,
+}
+
+/// implementation of player
+impl Player {
+    /// player from challengee
+    pub fn from_challengee(challengee: &Challengee) -> Player {
+        match challengee {
+            LightUser(user) => Player {
+                name: user.username.to_owned(),
+                rating: user.rating,
+                title: user.title.to_owned(),
+                ai_level: None,
+            },
+            StockFish(sf) => Player {
+                name: format!("Stockfish AI level {}", sf.ai_level),
+                rating: None,
+                title: None,
+                ai_level: Some(sf.ai_level),
+            },
+        }
+    }
+
+    /// true if player is a bot or the lichess AI
+    pub fn is_bot(&self) -> bool {
+        self.ai_level.is_some() || (self.title.as_deref() == Some("BOT"))
+    }
+}
+
+/// game info known at game start
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameInfo {
+    /// game id
+    pub id: String,
+    /// white player
+    pub white: Player,
+    /// black player
+    pub black: Player,
+    /// bot plays white
+    pub bot_white: bool,
+    /// speed ( ultraBullet, bullet, blitz, rapid, classical, correspondence )
+    pub speed: String,
+    /// rated
+    pub rated: bool,
+    /// variant key
+    pub variant: String,
+    /// initial fen
+    pub initial_fen: String,
+    /// initial time and increment in milliseconds, None for unlimited games
+    pub time_control: Option<(usize, usize)>,
+    /// creation time
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// implementation of game info
+impl GameInfo {
+    /// game info from game full event
+    pub fn from_game_full<T>(game_full: &GameFull, bot_name: T) -> GameInfo
+    where
+        T: core::fmt::Display,
+    {
+        let white = Player::from_challengee(&game_full.white);
+        let black = Player::from_challengee(&game_full.black);
+
+        let bot_white = black.name != bot_name.to_string();
+
+        GameInfo {
+            id: game_full.id.to_owned(),
+            white,
+            black,
+            bot_white,
+            speed: game_full.speed.to_owned(),
+            rated: game_full.rated,
+            variant: game_full.variant.key.to_owned(),
+            initial_fen: game_full.initial_fen.to_owned(),
+            time_control: game_full.clock.as_ref().map(|clock| {
+                (
+                    clock.initial.unwrap_or(0) as usize,
+                    clock.increment.unwrap_or(0) as usize,
+                )
+            }),
+            created_at: game_full.created_at,
+        }
+    }
+
+    /// bot player
+    pub fn bot(&self) -> &Player {
+        match self.bot_white {
+            true => &self.white,
+            _ => &self.black,
+        }
+    }
+
+    /// opponent player
+    pub fn opponent(&self) -> &Player {
+        match self.bot_white {
+            true => &self.black,
+            _ => &self.white,
+        }
+    }
+
+    /// true if white makes the first move
+    pub fn white_starts(&self) -> bool {
+        self.initial_fen.split_whitespace().nth(1) != Some("b")
+    }
+
+    /// true if the move at ply is made by white
+    pub fn white_moves_at(&self, ply: usize) -> bool {
+        ply % 2 == usize::from(!self.white_starts())
+    }
+}
+
+/// how a game ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Termination {
+    /// aborted before both sides moved
+    Aborted,
+    /// checkmate
+    Mate,
+    /// resignation
+    Resign,
+    /// stalemate
+    Stalemate,
+    /// opponent left the game and victory was claimed
+    Timeout,
+    /// draw by agreement, repetition, fifty moves or insufficient material
+    Draw,
+    /// flag fall
+    OutOfTime,
+    /// cheat detected
+    Cheat,
+    /// a player did not make the first move
+    NoStart,
+    /// variant specific end
+    VariantEnd,
+    /// unknown end
+    UnknownFinish,
+}
+
+/// implementation of termination
+impl Termination {
+    /// termination of lichess game status, None if the game is not over
+    pub fn from_status<T>(status: T) -> Option<Termination>
+    where
+        T: core::fmt::Display,
+    {
+        match status.to_string().as_str() {
+            "created" | "started" => None,
+            "aborted" => Some(Termination::Aborted),
+            "mate" => Some(Termination::Mate),
+            "resign" => Some(Termination::Resign),
+            "stalemate" => Some(Termination::Stalemate),
+            "timeout" => Some(Termination::Timeout),
+            "draw" => Some(Termination::Draw),
+            "outoftime" => Some(Termination::OutOfTime),
+            "cheat" => Some(Termination::Cheat),
+            "noStart" => Some(Termination::NoStart),
+            "variantEnd" => Some(Termination::VariantEnd),
+            _ => Some(Termination::UnknownFinish),
+        }
+    }
+
+    /// true if the game was played to a result
+    pub fn has_result(&self) -> bool {
+        !matches!(
+            self,
+            Termination::Aborted | Termination::NoStart | Termination::UnknownFinish
+        )
+    }
+}
+
+/// result of a game from the bot's point of view
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BotResult {
+    /// bot won
+    Win,
+    /// draw
+    Draw,
+    /// bot lost
+    Loss,
+}
+
+/// implementation of bot result
+impl BotResult {
+    /// score of result ( win 1, draw 0.5, loss 0 )
+    pub fn score(&self) -> f64 {
+        match self {
+            BotResult::Win => 1.0,
+            BotResult::Draw => 0.5,
+            BotResult::Loss => 0.0,
+        }
+    }
+}
+
+/// outcome of a finished game
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameOutcome {
+    /// how the game ended
+    pub termination: Termination,
+    /// winner color, white or black, None for draws and games without result
+    pub winner: Option<String>,
+    /// result from the bot's point of view, None for games without result
+    pub result: Option<BotResult>,
+}
+
+/// implementation of game outcome
+impl GameOutcome {
+    /// outcome of lichess game status and winner, None if the game is not over
+    pub fn from_status<T>(status: T, winner: Option<&str>, bot_white: bool) -> Option<GameOutcome>
+    where
+        T: core::fmt::Display,
+    {
+        let termination = Termination::from_status(status)?;
+
+        let bot_color = match bot_white {
+            true => "white",
+            _ => "black",
+        };
+
+        let result = match (termination.has_result(), winner) {
+            (false, _) => None,
+            (_, Some(winner)) if winner == bot_color => Some(BotResult::Win),
+            (_, Some(_)) => Some(BotResult::Loss),
+            _ => Some(BotResult::Draw),
+        };
+
+        Some(GameOutcome {
+            termination,
+            winner: winner.map(|winner| winner.to_string()),
+            result,
+        })
+    }
+
+    /// pgn result tag, * for games without result
+    pub fn pgn_result(&self) -> String {
+        match (self.result, self.winner.as_deref()) {
+            (None, _) => "*",
+            (_, Some("white")) => "1-0",
+            (_, Some("black")) => "0-1",
+            _ => "1/2-1/2",
+        }
+        .to_string()
+    }
+}
+
+/// kind of incident during a game
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IncidentKind {
+    /// engine process died or failed to answer
+    EngineCrash,
+    /// engine process was restarted
+    EngineRestart,
+    /// switched to backup engine
+    BackupEngine,
+    /// move source proposed an illegal move
+    IllegalMove,
+    /// move submission failed, but can be retried
+    MoveRetry,
+    /// move submission was rejected
+    MoveRejected,
+    /// no game state confirmed a submitted move
+    MoveUnconfirmed,
+    /// book move failed the engine check
+    BookMoveRejected,
+    /// moves were taken back
+    Takeback,
+}
+
+/// incident during a game
+#[derive(Debug, Clone, PartialEq)]
+pub struct Incident {
+    /// number of moves played when the incident happened
+    pub ply: usize,
+    /// name of move source involved
+    pub source: String,
+    /// kind
+    pub kind: IncidentKind,
+    /// description
+    pub message: String,
+}
+
+/// implementation of incident
+impl Incident {
+    /// create incident
+    pub fn new<S, M>(ply: usize, source: S, kind: IncidentKind, message: M) -> Incident
+    where
+        S: core::fmt::Display,
+        M: core::fmt::Display,
+    {
+        Incident {
+            ply,
+            source: source.to_string(),
+            kind,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// move played by the bot, with how it was found
+#[derive(Debug, Clone)]
+pub struct MoveDecision {
+    /// number of moves played before the move
+    pub ply: usize,
+    /// move in uci notation
+    pub uci: String,
+    /// name of move source
+    pub source: String,
+    /// evaluation from the point of view of the bot
+    pub score: Option<Score>,
+    /// search depth
+    pub depth: Option<usize>,
+    /// time used for finding the move in milliseconds
+    pub time: usize,
+}
+
+/// record of a game, collected while playing
+#[derive(Debug, Clone)]
+pub struct GameRecord {
+    /// game info
+    pub info: GameInfo,
+    /// incidents
+    pub incidents: Vec<Incident>,
+    /// moves played by the bot
+    pub decisions: Vec<MoveDecision>,
+    /// remaining time of the player after the move at each ply in milliseconds, if known
+    pub clocks: Vec<Option<usize>>,
+    /// moves of last game state, space separated uci
+    pub moves: String,
+    /// status of last game state
+    pub status: String,
+    /// winner color of last game state
+    pub winner: Option<String>,
+}
+
+/// implementation of game record
+impl GameRecord {
+    /// create game record
+    pub fn new(info: GameInfo) -> GameRecord {
+        GameRecord {
+            info,
+            incidents: vec![],
+            decisions: vec![],
+            clocks: vec![],
+            moves: String::new(),
+            status: "created".to_string(),
+            winner: None,
+        }
+    }
+
+    /// update moves, status, winner and clocks from game state,
+    /// decisions of taken back moves are dropped
+    pub fn update(&mut self, state: &GameState) {
+        self.moves = state.moves.to_owned();
+        self.status = state.status.to_owned();
+        self.winner = state.winner.to_owned();
+
+        let plies = self.moves.split_whitespace().count();
+
+        self.decisions.retain(|decision| decision.ply < plies);
+        self.clocks.resize(plies, None);
+
+        if plies > 0 {
+            let clock = Clock::from_state(state);
+
+            self.clocks[plies - 1] = Some(clock.time(self.info.white_moves_at(plies - 1)));
+        }
+    }
+
+    /// add move played by the bot
+    pub fn decision(&mut self, decision: MoveDecision) {
+        self.decisions.push(decision);
+    }
+
+    /// decision of move at ply, None if the opponent made the move or it is unknown
+    pub fn decision_at(&self, ply: usize) -> Option<&MoveDecision> {
+        self.decisions.iter().find(|decision| decision.ply == ply)
+    }
+
+    /// outcome of last game state, None if the game is not over
+    pub fn outcome(&self) -> Option<GameOutcome> {
+        GameOutcome::from_status(&self.status, self.winner.as_deref(), self.info.bot_white)
+    }
+
+    /// result from the bot's point of view ( win 1, draw 0.5, loss 0 ),
+    /// None if the game is not over or was not played
+    pub fn bot_result(&self) -> Option<f64> {
+        self.outcome()
+            .and_then(|outcome| outcome.result)
+            .map(|result| result.score())
+    }
+
+    /// add incident
+    pub fn incident(&mut self, incident: Incident) {
+        if log_enabled!(Level::Info) {
+            info!("incident in game {} : {:?}", self.info.id, incident);
+        }
+
+        self.incidents.push(incident);
+    }
+}
@@ -0,0 +1,227 @@
+use log::{info, log_enabled, Level};
+
+use shakmaty::fen;
+use shakmaty::uci::Uci;
+use shakmaty::{Chess, Color, Position, Setup};
+
+use crate::book::BookCandidate;
+
+/// statistics of a move played by the bot
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LearnedMove {
+    /// number of games
+    pub games: usize,
+    /// number of games, decayed
+    pub plays: f64,
+    /// sum of results from the bot's point of view ( win 1, draw 0.5, loss 0 ), decayed
+    pub score: f64,
+}
+
+/// implementation of learned move
+impl LearnedMove {
+    /// performance in percent
+    pub fn perf(&self) -> usize {
+        match self.plays > 0.0 {
+            true => ((self.score / self.plays) * 100.0).round() as usize,
+            _ => 0,
+        }
+    }
+}
+
+/// book learned from the results of the bot's own games
+#[derive(Debug, Clone, PartialEq)]
+pub struct LearningBook {
+    /// book file, the book is saved here after every update
+    pub path: Option<String>,
+    /// moves played by the bot, by epd and uci
+    pub positions:
+        std::collections::HashMap<String, std::collections::HashMap<String, LearnedMove>>,
+    /// factor statistics are multiplied with before learning a game, 1 keeps them forever
+    pub decay: f64,
+    /// min number of games of a move for using it
+    pub min_games: usize,
+}
+
+/// write book file content, written to a temporary file first and then renamed,
+/// so that the file is never left half written
+pub fn write_learning_book<P, C>(path: P, content: C) -> std::io::Result<()>
+where
+    P: core::fmt::Display,
+    C: AsRef<[u8]>,
+{
+    let tmp_path = format!("{}.tmp", path);
+
+    std::fs::write(&tmp_path, content)?;
+
+    std::fs::rename(&tmp_path, path.to_string())
+}
+
+/// implementation of learning book
+impl LearningBook {
+    /// create empty learning book
+    pub fn new() -> LearningBook {
+        LearningBook {
+            path: None,
+            positions: std::collections::HashMap::new(),
+            decay: 0.98,
+            min_games: 2,
+        }
+    }
+
+    /// set decay and return self
+    pub fn decay(mut self, decay: f64) -> LearningBook {
+        self.decay = decay.clamp(0.0, 1.0);
+
+        self
+    }
+
+    /// set min games and return self
+    pub fn min_games(mut self, min_games: usize) -> LearningBook {
+        self.min_games = min_games;
+
+        self
+    }
+
+    /// load book file and return self, a missing file is an empty book
+    pub fn load<T>(mut self, path: T) -> std::io::Result<LearningBook>
+    where
+        T: core::fmt::Display,
+    {
+        let path = path.to_string();
+
+        self.path = Some(path.to_owned());
+
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err),
+        };
+
+        for line in content.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+
+            if let [epd, uci, games, plays, score] = fields.as_slice() {
+                if let (Ok(games), Ok(plays), Ok(score)) =
+                    (games.parse(), plays.parse(), score.parse())
+                {
+                    self.positions.entry(epd.to_string()).or_default().insert(
+                        uci.to_string(),
+                        LearnedMove {
+                            games,
+                            plays,
+                            score,
+                        },
+                    );
+                }
+            }
+        }
+
+        if log_enabled!(Level::Info) {
+            info!(
+                "learning book {} loaded with {} positions",
+                path,
+                self.positions.len()
+            );
+        }
+
+        Ok(self)
+    }
+
+    /// book file content, tab separated epd, uci, games, plays and score lines sorted
+    pub fn content(&self) -> String {
+        let mut lines: Vec<String> = self
+            .positions
+            .iter()
+            .flat_map(|(epd, moves)| {
+                moves.iter().map(move |(uci, m)| {
+                    format!("{}\t{}\t{}\t{}\t{}", epd, uci, m.games, m.plays, m.score)
+                })
+            })
+            .collect();
+
+        lines.sort();
+
+        lines.join("\n")
+    }
+
+    /// save book to its file, see write_learning_book
+    pub fn save(&self) -> std::io::Result<()> {
+        match &self.path {
+            Some(path) => write_learning_book(path, self.content()),
+            _ => Ok(()),
+        }
+    }
+
+    /// learn result of a game from the bot's point of view ( win 1, draw 0.5, loss 0 ),
+    /// moves are space separated uci from the starting position, only the first max ply moves count
+    pub fn learn<T>(&mut self, moves: T, bot_white: bool, result: f64, max_ply: usize)
+    where
+        T: core::fmt::Display,
+    {
+        for moves in self.positions.values_mut() {
+            for m in moves.values_mut() {
+                m.plays *= self.decay;
+                m.score *= self.decay;
+            }
+        }
+
+        let bot_color = match bot_white {
+            true => Color::White,
+            _ => Color::Black,
+        };
+
+        let mut pos = Chess::default();
+
+        for uci_str in moves.to_string().split_whitespace().take(max_ply) {
+            let m = match uci_str
+                .parse::<Uci>()
+                .ok()
+                .and_then(|uci| uci.to_move(&pos).ok())
+            {
+                Some(m) => m,
+                _ => break,
+            };
+
+            if pos.turn() == bot_color {
+                let learned = self
+                    .positions
+                    .entry(fen::epd(&pos))
+                    .or_default()
+                    .entry(uci_str.to_string())
+                    .or_default();
+
+                learned.games += 1;
+                learned.plays += 1.0;
+                learned.score += result;
+            }
+
+            pos.play_unchecked(&m);
+        }
+    }
+
+    /// candidate moves of position with at least min games
+    pub fn candidates<T>(&self, epd: T) -> Vec<BookCandidate>
+    where
+        T: AsRef<str>,
+    {
+        match self.positions.get(epd.as_ref()) {
+            Some(moves) => moves
+                .iter()
+                .filter(|(_, m)| m.games >= self.min_games)
+                .map(|(uci, m)| BookCandidate {
+                    uci: uci.to_owned(),
+                    weight: (m.plays * 100.0).round() as usize,
+                    perf: Some(m.perf()),
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+}
+
+/// default learning book, empty
+impl Default for LearningBook {
+    fn default() -> LearningBook {
+        LearningBook::new()
+    }
+}
@@ -2,7 +2,7 @@
 //! # Examples
 //!
 //!
-//!```
+//!```no_run
 //!use log::{info, log_enabled, Level};
 //!
 //!extern crate env_logger;
@@ -54,4 +54,21 @@
 
 
 // lib
+pub mod analysis;
+pub mod archive;
+pub mod book;
+pub mod botapi;
+pub mod chat;
+pub mod game;
+pub mod learn;
 pub mod lichessbot;
+pub mod movesource;
+pub mod opponent;
+pub mod policy;
+pub mod polyglot;
+pub mod ponder;
+pub mod repertoire;
+pub mod search;
+pub mod store;
+pub mod submit;
+pub mod tablebase;
@@ -2,19 +2,28 @@ use log::{debug, info, log_enabled, Level};
 
 use futures_util::TryStreamExt;
 use licoricedev::client::Lichess;
-use licoricedev::models::board::Challengee::{LightUser, StockFish};
 use licoricedev::models::board::{BoardState, Event};
 
 use shakmaty::fen;
 use shakmaty::fen::Fen;
 use shakmaty::uci::{IllegalUciError, Uci};
-use shakmaty::{Chess, Color, Position};
-
-use rand::prelude::*;
+use shakmaty::{Chess, Position, Setup};
 
 use envor::envor::*;
-use pgnparse::parser::*;
-use uciengine::uciengine::*;
+
+use crate::analysis::*;
+use crate::archive::*;
+use crate::book::*;
+use crate::botapi::*;
+use crate::chat::*;
+use crate::game::*;
+use crate::movesource::*;
+use crate::opponent::*;
+use crate::policy::*;
+use crate::repertoire::*;
+use crate::store::*;
+use crate::submit::*;
+use crate::tablebase::*;
 
 /// make uci moves from starting position and return fen of resulting position
 pub fn make_uci_moves<T>(ucis_str: T) -> Result<(String, String), Box<dyn std::error::Error>>
@@ -24,8 +33,8 @@ where
     let ucis_str = format!("{}", ucis_str);
 
     let mut pos = Chess::default();
-    if ucis_str.len() > 0 {
-        for uci_str in ucis_str.split(" ") {
+    if !ucis_str.is_empty() {
+        for uci_str in ucis_str.split(' ') {
             let uci: Uci = uci_str.parse()?;
             let m = uci.to_move(&pos.to_owned())?;
             match pos.to_owned().play(&m) {
@@ -37,6 +46,12 @@ where
     Ok((fen::fen(&pos), fen::epd(&pos)))
 }
 
+/// control command reloading the books
+pub const RELOAD_BOOKS_COMMAND: &str = "reload books";
+
+/// function called with the record of every finished game
+pub type GameEndHook = Box<dyn Fn(&GameRecord, &GameOutcome) + Send + Sync>;
+
 /// bot state
 #[derive(Debug, Clone)]
 pub struct BotState {
@@ -46,18 +61,30 @@ pub struct BotState {
     pub engine_thinking: bool,
     /// streaming
     pub streaming: bool,
+    /// number of games won
+    pub wins: usize,
+    /// number of games drawn
+    pub draws: usize,
+    /// number of games lost
+    pub losses: usize,
 }
 
-/// bot state implementation
-impl BotState {
-    pub fn default() -> BotState {
+/// default bot state
+impl Default for BotState {
+    fn default() -> BotState {
         BotState {
             current_fen: None,
             engine_thinking: false,
             streaming: false,
+            wins: 0,
+            draws: 0,
+            losses: 0,
         }
     }
+}
 
+/// bot state implementation
+impl BotState {
     /// set current fen
     pub fn set_current_fen(mut self, fen: Option<String>) -> BotState {
         self.current_fen = fen;
@@ -78,18 +105,51 @@ impl BotState {
 
         self
     }
+
+    /// set won, drawn and lost games
+    pub fn set_stats(mut self, stats: &GameStats) -> BotState {
+        self.wins = stats.wins;
+        self.draws = stats.draws;
+        self.losses = stats.losses;
+
+        self
+    }
+
+    /// count result of game outcome
+    pub fn add_outcome(mut self, outcome: &GameOutcome) -> BotState {
+        match outcome.result {
+            Some(BotResult::Win) => self.wins += 1,
+            Some(BotResult::Draw) => self.draws += 1,
+            Some(BotResult::Loss) => self.losses += 1,
+            _ => {}
+        }
+
+        self
+    }
 }
 
 /// lichess bot
 pub struct LichessBot {
     /// lichess client
     pub lichess: Lichess,
+    /// lichess bot api requests the lichess client lacks
+    pub bot_api: BotApi,
     /// lichess username of bot
     pub bot_name: String,
     /// engine executable name ( optional )
     pub engine_name: Option<String>,
+    /// backup engine executable name, used when the engine keeps crashing ( optional )
+    pub backup_engine_name: Option<String>,
+    /// number of engine restarts in a game before switching to the backup engine
+    pub max_engine_restarts: usize,
+    /// max attempts of submitting a move
+    pub max_move_attempts: usize,
     /// uci options
     pub uci_options: std::collections::HashMap<String, String>,
+    /// uci options by opponent, overriding uci options in games against them
+    pub opponent_options: OpponentOptions,
+    /// strength limiting in casual games against humans
+    pub handicap: Handicap,
     /// enable classical
     pub enable_classical: bool,
     /// enable rapid
@@ -104,10 +164,50 @@ pub struct LichessBot {
     pub enable_casual: bool,
     /// disable rated
     pub disable_rated: bool,
-    /// book
-    pub book: Book,
+    /// max number of moves played for using a book, unless set for the book
+    pub max_book_depth: usize,
+    /// books in order of priority
+    pub books: BookList,
+    /// interval of checking book files for changes in seconds, 0 for never
+    pub book_watch_interval: u64,
+    /// depth of engine search checking book moves, 0 for no check
+    pub book_verify_depth: usize,
+    /// max eval drop of a book move below the best move in centipawns
+    pub book_verify_margin: i32,
+    /// file rejected book moves are appended to ( optional )
+    pub book_verify_log: Option<String>,
+    /// seed of book move selection, for reproducible games ( optional )
+    pub book_seed: Option<u64>,
+    /// opening repertoire, played before books
+    pub repertoire: Repertoire,
+    /// syzygy tablebase ( optional )
+    pub tablebase: Option<std::sync::Arc<SyzygyTablebase>>,
+    /// max depth of the built in search
+    pub search_depth: usize,
+    /// chat settings
+    pub chat: ChatConfig,
+    /// resign policy
+    pub resign: ResignPolicy,
+    /// draw policy
+    pub draw: DrawPolicy,
+    /// takeback policy
+    pub takeback: TakebackPolicy,
+    /// archive finished games are saved to ( optional )
+    pub pgn_archive: Option<PgnArchive>,
+    /// store of games, challenges and move decisions ( optional )
+    pub store: Option<GameStore>,
+    /// folder post game analysis reports are written to ( optional )
+    pub analysis_dir: Option<String>,
+    /// search depth of post game analysis
+    pub analysis_depth: usize,
     /// state
     pub state: std::sync::Arc<tokio::sync::Mutex<BotState>>,
+    /// names of move sources in the order they are asked for a move
+    pub move_source_order: Vec<String>,
+    /// custom move sources
+    pub move_sources: Vec<Box<dyn MoveSource>>,
+    /// functions called when a game is over
+    pub game_end_hooks: Vec<GameEndHook>,
 }
 
 macro_rules! gen_set_props {
@@ -143,6 +243,13 @@ gen_set_props!(
     => disable_rated
 );
 
+/// default lichess bot, configured from environment
+impl Default for LichessBot {
+    fn default() -> LichessBot {
+        LichessBot::new()
+    }
+}
+
 /// lichess bot implementation
 impl LichessBot {
     /// create new lichess bot
@@ -153,9 +260,28 @@ impl LichessBot {
 
         let mut bot = LichessBot {
             lichess: Lichess::new(std::env::var("RUST_BOT_TOKEN").unwrap()),
+            bot_api: BotApi::new(std::env::var("RUST_BOT_TOKEN").unwrap()),
             bot_name: bot_name.to_owned(),
             engine_name: std::env::var("RUST_BOT_ENGINE_NAME").ok(),
+            backup_engine_name: std::env::var("RUST_BOT_BACKUP_ENGINE_NAME").ok(),
+            max_engine_restarts: env_or("RUST_BOT_MAX_ENGINE_RESTARTS", 2),
+            max_move_attempts: env_or("RUST_BOT_MAX_MOVE_ATTEMPTS", 5),
             uci_options: std::collections::HashMap::new(),
+            opponent_options: OpponentOptions::new()
+                .bands(env_string_or("RUST_BOT_RATING_OPTIONS", ""))
+                .users(env_string_or("RUST_BOT_OPPONENT_OPTIONS", ""))
+                .min_history(env_or("RUST_BOT_HISTORY_GAMES", 5)),
+            handicap: Handicap::new()
+                .mode(
+                    HandicapMode::from_name(env_string_or("RUST_BOT_HANDICAP", "off"))
+                        .unwrap_or(HandicapMode::Off),
+                )
+                .elo_offset(env_or("RUST_BOT_HANDICAP_ELO_OFFSET", 0))
+                .multipv(
+                    env_or("RUST_BOT_HANDICAP_MULTIPV", 4),
+                    env_or("RUST_BOT_HANDICAP_MARGIN", 100),
+                    env_or("RUST_BOT_HANDICAP_TEMPERATURE", 50.0),
+                ),
             enable_classical: false,
             enable_rapid: false,
             disable_blitz: false,
@@ -163,27 +289,576 @@ impl LichessBot {
             enable_ultrabullet: false,
             enable_casual: false,
             disable_rated: false,
-            book: Book::new().me(bot_name.to_owned()),
+            max_book_depth,
+            books: BookList::new(),
+            book_watch_interval: env_or("RUST_BOT_BOOK_WATCH", 0),
+            book_verify_depth: env_or("RUST_BOT_BOOK_VERIFY_DEPTH", 0),
+            book_verify_margin: env_or("RUST_BOT_BOOK_VERIFY_MARGIN", 100),
+            book_verify_log: std::env::var("RUST_BOT_BOOK_VERIFY_LOG").ok(),
+            book_seed: std::env::var("RUST_BOT_BOOK_SEED")
+                .ok()
+                .and_then(|seed| seed.parse().ok()),
+            repertoire: Repertoire::new()
+                .lines(true, env_string_or("RUST_BOT_REPERTOIRE_WHITE", ""))
+                .lines(false, env_string_or("RUST_BOT_REPERTOIRE_BLACK", "")),
+            tablebase: None,
+            search_depth: env_or("RUST_BOT_SEARCH_DEPTH", 64),
+            chat: ChatConfig::new()
+                .greeting(env_string_or("RUST_BOT_CHAT_GREETING", ""))
+                .goodbye(env_string_or("RUST_BOT_CHAT_GOODBYE", ""))
+                .rooms(env_string_or("RUST_BOT_CHAT_ROOMS", "player,spectator"))
+                .commands(env_or("RUST_BOT_CHAT_COMMANDS", true))
+                .rate_limit(
+                    env_or("RUST_BOT_CHAT_INTERVAL", 5000),
+                    env_or("RUST_BOT_CHAT_MAX_ANSWERS", 20),
+                ),
+            resign: ResignPolicy::new()
+                .after(
+                    env_or("RUST_BOT_RESIGN_MOVES", 0),
+                    env_or("RUST_BOT_RESIGN_SCORE", -1000),
+                )
+                .speed_scores(env_string_or("RUST_BOT_RESIGN_SPEED_SCORES", ""))
+                .casual(env_or("RUST_BOT_RESIGN_CASUAL", true)),
+            draw: DrawPolicy::new()
+                .after(
+                    env_or("RUST_BOT_DRAW_MOVES", 0),
+                    env_or("RUST_BOT_DRAW_MARGIN", 20),
+                    env_or("RUST_BOT_DRAW_MIN_MOVE", 30),
+                )
+                .tablebase(env_or("RUST_BOT_DRAW_TABLEBASE", true))
+                .accept_offer(
+                    env_or("RUST_BOT_DRAW_ACCEPT", true),
+                    env_or("RUST_BOT_DRAW_OFFER", true),
+                ),
+            takeback: TakebackPolicy::from_name(env_string_or("RUST_BOT_TAKEBACKS", "decline"))
+                .unwrap_or_default(),
+            pgn_archive: std::env::var("RUST_BOT_PGN_DIR").ok().map(PgnArchive::new),
+            store: None,
+            analysis_dir: std::env::var("RUST_BOT_ANALYSIS_DIR").ok(),
+            analysis_depth: env_or("RUST_BOT_ANALYSIS_DEPTH", 12),
             state: std::sync::Arc::new(tokio::sync::Mutex::new(BotState::default())),
+            move_source_order: vec![],
+            move_sources: vec![],
+            game_end_hooks: vec![],
         }
-        .max_book_depth(max_book_depth);
-
-        bot.book
-            .parse(env_string_or("RUST_BOT_BOOK_PGN", "book.pgn"));
+        .move_source_order(env_string_or(
+            "RUST_BOT_MOVE_SOURCES",
+            "repertoire,book,tablebase,engine,search,random",
+        ));
 
         if log_enabled!(Level::Info) {
-            info!("max book depth {}", bot.book.max_depth);
+            info!("max book depth {}", max_book_depth);
+            info!(
+                "repertoire lines white {} black {}",
+                bot.repertoire.white.len(),
+                bot.repertoire.black.len()
+            );
+        }
+
+        if let Ok(path) = std::env::var("RUST_BOT_SYZYGY_PATH") {
+            bot = bot.syzygy_path(path);
+        }
+
+        if let Ok(path) = std::env::var("RUST_BOT_DB") {
+            bot = bot.database(path);
+        }
+
+        match std::env::var("RUST_BOT_BOOKS") {
+            Ok(specs) => {
+                for spec in specs.split(';').filter(|spec| !spec.trim().is_empty()) {
+                    bot = bot.book(spec);
+                }
+            }
+            _ => {
+                if let Ok(path) = std::env::var("RUST_BOT_LEARN_BOOK") {
+                    bot = bot.book(format!(
+                        "{} decay={} min_games={}",
+                        path,
+                        env_string_or("RUST_BOT_LEARN_DECAY", "0.98"),
+                        env_string_or("RUST_BOT_LEARN_MIN_GAMES", "2")
+                    ));
+                }
+
+                if let Some(book) = ScopedBook::load(
+                    env_string_or("RUST_BOT_BOOK_PGN", "book.pgn"),
+                    &bot_name,
+                    max_book_depth,
+                ) {
+                    bot = bot.scoped_book(
+                        book.selection(BookSelection::Mixed(env_or("RUST_BOT_MIXED", 90))),
+                    );
+                }
+
+                if let Ok(path) = std::env::var("RUST_BOT_BOOK_BIN") {
+                    if let Some(book) = ScopedBook::load(
+                        path,
+                        &bot_name,
+                        env_or("RUST_BOT_BOOK_BIN_DEPTH", max_book_depth),
+                    ) {
+                        bot = bot.scoped_book(
+                            book.selection(
+                                BookSelection::from_name(env_string_or(
+                                    "RUST_BOT_BOOK_BIN_SELECTION",
+                                    "weighted",
+                                ))
+                                .unwrap_or(BookSelection::Weighted),
+                            ),
+                        );
+                    }
+                }
+            }
         }
 
         bot
     }
 
-    /// set max book depth
+    /// set max book depth of all books and of books added later
     pub fn max_book_depth<T>(mut self, max_book_depth: T) -> LichessBot
     where
         T: core::fmt::Display,
     {
-        self.book = self.book.max_depth(max_book_depth);
+        if let Ok(max_book_depth) = max_book_depth.to_string().parse() {
+            self.max_book_depth = max_book_depth;
+
+            self.books.set(
+                self.books
+                    .snapshot()
+                    .into_iter()
+                    .map(|book| book.max_ply(max_book_depth))
+                    .collect(),
+            );
+        }
+
+        self
+    }
+
+    /// add book from spec with lowest priority, see ScopedBook::from_spec
+    pub fn book<T>(self, spec: T) -> LichessBot
+    where
+        T: core::fmt::Display,
+    {
+        let book = ScopedBook::from_spec(&spec, &self.bot_name, self.max_book_depth);
+
+        match book {
+            Some(book) => self.scoped_book(book),
+            _ => {
+                if log_enabled!(Level::Info) {
+                    info!("could not add book {}", spec);
+                }
+
+                self
+            }
+        }
+    }
+
+    /// add scoped book with lowest priority
+    pub fn scoped_book(self, book: ScopedBook) -> LichessBot {
+        if log_enabled!(Level::Info) {
+            info!(
+                "book {} scope {:?} max ply {} policy {:?} speed policies {:?}",
+                book.name, book.scope, book.max_ply, book.policy, book.speed_policies
+            );
+        }
+
+        self.books.push(book);
+
+        self
+    }
+
+    /// remove all books
+    pub fn clear_books(self) -> LichessBot {
+        self.books.set(vec![]);
+
+        self
+    }
+
+    /// set interval of checking book files for changes in seconds, 0 for never
+    pub fn book_watch_interval(mut self, book_watch_interval: u64) -> LichessBot {
+        self.book_watch_interval = book_watch_interval;
+
+        self
+    }
+
+    /// check book moves with an engine search of depth, rejecting moves with an eval drop
+    /// of more than margin centipawns below the best move, depth 0 for no check
+    pub fn book_verify(mut self, depth: usize, margin: i32) -> LichessBot {
+        self.book_verify_depth = depth;
+        self.book_verify_margin = margin;
+
+        self
+    }
+
+    /// set file rejected book moves are appended to
+    pub fn book_verify_log<T>(mut self, book_verify_log: T) -> LichessBot
+    where
+        T: core::fmt::Display,
+    {
+        self.book_verify_log = Some(book_verify_log.to_string());
+
+        self
+    }
+
+    /// set seed of book move selection and return self
+    pub fn book_seed(mut self, book_seed: u64) -> LichessBot {
+        self.book_seed = Some(book_seed);
+
+        self
+    }
+
+    /// book move verifier of a game, if an engine is available and verification is enabled
+    fn book_verifier(&self) -> Option<BookVerifier> {
+        match (&self.engine_name, self.book_verify_depth) {
+            (Some(engine_name), depth) if depth > 0 => Some(
+                BookVerifier::new(
+                    engine_name,
+                    self.uci_options.clone(),
+                    depth,
+                    self.book_verify_margin,
+                )
+                .log_file(self.book_verify_log.to_owned()),
+            ),
+            _ => None,
+        }
+    }
+
+    /// add repertoire lines for color and return self, lines are a pgn file or san or uci moves
+    /// from the starting position separated by ;, for example `1. e4 e5 2. Nf3;1. e4 c5 2. Nf3`
+    pub fn repertoire<T>(mut self, bot_white: bool, lines: T) -> LichessBot
+    where
+        T: core::fmt::Display,
+    {
+        self.repertoire = self.repertoire.lines(bot_white, lines);
+
+        self
+    }
+
+    /// load syzygy tablebase from directories separated by ; and return self,
+    /// max pieces are taken from RUST_BOT_SYZYGY_PIECES, the tablebase is not used if loading fails
+    pub fn syzygy_path<T>(mut self, path: T) -> LichessBot
+    where
+        T: core::fmt::Display,
+    {
+        match SyzygyTablebase::new(&path) {
+            Ok(tablebase) => {
+                self.tablebase = Some(std::sync::Arc::new(
+                    tablebase.max_pieces(env_or("RUST_BOT_SYZYGY_PIECES", 7)),
+                ))
+            }
+            Err(err) => {
+                if log_enabled!(Level::Info) {
+                    info!("could not load syzygy tablebase : {}", err);
+                }
+            }
+        }
+
+        self
+    }
+
+    /// set max depth of the built in search and return self
+    pub fn search_depth(mut self, search_depth: usize) -> LichessBot {
+        self.search_depth = search_depth;
+
+        self
+    }
+
+    /// set chat settings and return self
+    pub fn chat(mut self, chat: ChatConfig) -> LichessBot {
+        self.chat = chat;
+
+        self
+    }
+
+    /// set resign policy and return self
+    pub fn resign(mut self, resign: ResignPolicy) -> LichessBot {
+        self.resign = resign;
+
+        self
+    }
+
+    /// set draw policy and return self
+    pub fn draw(mut self, draw: DrawPolicy) -> LichessBot {
+        self.draw = draw;
+
+        self
+    }
+
+    /// set takeback policy and return self
+    pub fn takeback(mut self, takeback: TakebackPolicy) -> LichessBot {
+        self.takeback = takeback;
+
+        self
+    }
+
+    /// open game store in database file and return self, won, drawn and lost games
+    /// of the store are counted in the state, the store is not used if opening fails
+    pub fn database<T>(mut self, path: T) -> LichessBot
+    where
+        T: core::fmt::Display,
+    {
+        match GameStore::open(&path).and_then(|store| Ok((store.totals()?, store))) {
+            Ok((totals, store)) => {
+                if log_enabled!(Level::Info) {
+                    info!("opened game store {} , {:?}", path, totals);
+                }
+
+                if let Ok(mut state) = self.state.try_lock() {
+                    *state = state.clone().set_stats(&totals);
+                }
+
+                self.store = Some(store);
+            }
+            Err(err) => {
+                if log_enabled!(Level::Info) {
+                    info!("could not open game store {} : {}", path, err);
+                }
+            }
+        }
+
+        self
+    }
+
+    /// set folder and search depth of post game analysis and return self
+    pub fn analysis<T>(mut self, dir: T, depth: usize) -> LichessBot
+    where
+        T: core::fmt::Display,
+    {
+        self.analysis_dir = Some(dir.to_string());
+        self.analysis_depth = depth;
+
+        self
+    }
+
+    /// set folder of pgn archive and return self
+    pub fn pgn_dir<T>(mut self, dir: T) -> LichessBot
+    where
+        T: core::fmt::Display,
+    {
+        self.pgn_archive = Some(PgnArchive::new(dir));
+
+        self
+    }
+
+    /// resign game, a failed request is only logged
+    async fn resign_game(&self, game_id: &str) {
+        let game_id = game_id.to_string();
+
+        // the api call panics on an unexpected response, so it runs in its own task
+        let result = tokio::spawn(async move {
+            let lichess = Lichess::new(std::env::var("RUST_BOT_TOKEN").unwrap());
+
+            lichess
+                .resign_bot_game(&game_id)
+                .await
+                .map_err(|err| format!("{:?}", err))
+        })
+        .await;
+
+        if log_enabled!(Level::Info) {
+            info!("resign result {:?}", result);
+        }
+    }
+
+    /// answer takeback proposal of the opponent by the takeback policy, a failed request is only logged
+    async fn answer_takeback(&self, game_id: &str, info: &GameInfo) {
+        let accept = self.takeback.accepts(info);
+
+        if log_enabled!(Level::Info) {
+            info!(
+                "takeback proposed by {} in game {} , accepting {}",
+                info.opponent().name,
+                game_id,
+                accept
+            );
+        }
+
+        let result = self.bot_api.handle_takeback(game_id, accept).await;
+
+        if log_enabled!(Level::Info) {
+            info!("takeback answer result {:?}", result);
+        }
+    }
+
+    /// opening of game as named by lichess, None if the game export fails
+    async fn fetch_opening(&self, game_id: &str) -> Option<GameOpening> {
+        let query = vec![("moves", "false"), ("opening", "true")];
+
+        match self
+            .lichess
+            .export_one_game_pgn(game_id, Some(&query))
+            .await
+        {
+            Ok(pgn) => GameOpening::from_pgn(pgn),
+            Err(err) => {
+                if log_enabled!(Level::Info) {
+                    info!("exporting game {} failed {:?}", game_id, err);
+                }
+
+                None
+            }
+        }
+    }
+
+    /// save finished game to the pgn archive and the store, failures are only logged
+    async fn save_game(&self, record: &GameRecord, outcome: &GameOutcome) {
+        let archive = self
+            .pgn_archive
+            .as_ref()
+            .filter(|_| outcome.result.is_some());
+
+        if archive.is_none() && self.store.is_none() {
+            return;
+        }
+
+        let opening = self.fetch_opening(&record.info.id).await;
+
+        if let Some(archive) = archive {
+            let pgn = game_pgn(record, outcome, self.chat_engine_name(), opening.as_ref());
+
+            if let Err(err) = archive.save(&record.info, pgn) {
+                if log_enabled!(Level::Info) {
+                    info!("saving game {} failed {}", record.info.id, err);
+                }
+            }
+        }
+
+        if let Some(store) = &self.store {
+            if let Err(err) = store.add_game(record, outcome, opening.as_ref()) {
+                if log_enabled!(Level::Info) {
+                    info!("storing game {} failed {}", record.info.id, err);
+                }
+            }
+        }
+    }
+
+    /// analyze the moves of the bot in finished game with the engine in the background
+    fn analyze_game(&self, record: &GameRecord, outcome: &GameOutcome) {
+        if let (Some(dir), Some(engine_name), Some(_)) =
+            (&self.analysis_dir, &self.engine_name, outcome.result)
+        {
+            if record.decisions.is_empty() {
+                return;
+            }
+
+            let analyzer = GameAnalyzer::new(
+                engine_name,
+                self.uci_options.clone(),
+                self.analysis_depth,
+                dir,
+            );
+
+            let record = record.clone();
+            let outcome = outcome.clone();
+            let display_name = self.chat_engine_name();
+
+            tokio::spawn(async move { analyzer.report(record, outcome, display_name).await });
+        }
+    }
+
+    /// engine name shown in chat and pgn, file name of the engine or the built in search
+    fn chat_engine_name(&self) -> String {
+        match &self.engine_name {
+            Some(engine_name) => std::path::Path::new(engine_name)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| engine_name.to_owned()),
+            _ => "the built in search".to_string(),
+        }
+    }
+
+    /// write chat messages of room and text in the background, failures are only logged
+    fn write_chat(&self, game_id: &str, messages: Vec<(String, String)>) {
+        if messages.is_empty() {
+            return;
+        }
+
+        let game_id = game_id.to_string();
+
+        tokio::spawn(async move {
+            let lichess = Lichess::new(std::env::var("RUST_BOT_TOKEN").unwrap());
+
+            for (room, text) in messages {
+                let result = lichess.write_in_bot_chat(&game_id, &room, &text).await;
+
+                if log_enabled!(Level::Info) {
+                    info!(
+                        "chat {} in {} : {} , result {:?}",
+                        game_id, room, text, result
+                    );
+                }
+            }
+        });
+    }
+
+    /// set move source order as comma separated list of move source names,
+    /// built in move sources are repertoire, book, tablebase, engine, search and random
+    pub fn move_source_order<T>(mut self, order: T) -> LichessBot
+    where
+        T: core::fmt::Display,
+    {
+        self.move_source_order = order
+            .to_string()
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect();
+
+        self
+    }
+
+    /// add custom move source, it is asked for a move right before the random move source,
+    /// or last if there is no random move source in the move source order
+    pub fn move_source<S>(mut self, source: S) -> LichessBot
+    where
+        S: MoveSource + 'static,
+    {
+        let name = source.name();
+
+        if !self.move_source_order.contains(&name) {
+            match self
+                .move_source_order
+                .iter()
+                .position(|name| name == "random")
+            {
+                Some(index) => self.move_source_order.insert(index, name),
+                _ => self.move_source_order.push(name),
+            }
+        }
+
+        self.move_sources.push(Box::new(source));
+
+        self
+    }
+
+    /// add function called with the record and outcome of every finished game
+    pub fn on_game_end<F>(mut self, hook: F) -> LichessBot
+    where
+        F: Fn(&GameRecord, &GameOutcome) + Send + Sync + 'static,
+    {
+        self.game_end_hooks.push(Box::new(hook));
+
+        self
+    }
+
+    /// set backup engine name
+    pub fn backup_engine_name<T>(mut self, backup_engine_name: T) -> LichessBot
+    where
+        T: core::fmt::Display,
+    {
+        self.backup_engine_name = Some(backup_engine_name.to_string());
+
+        self
+    }
+
+    /// set max engine restarts in a game
+    pub fn max_engine_restarts(mut self, max_engine_restarts: usize) -> LichessBot {
+        self.max_engine_restarts = max_engine_restarts;
+
+        self
+    }
+
+    /// set max attempts of submitting a move
+    pub fn max_move_attempts(mut self, max_move_attempts: usize) -> LichessBot {
+        self.max_move_attempts = max_move_attempts;
 
         self
     }
@@ -202,6 +877,159 @@ impl LichessBot {
         self
     }
 
+    /// set uci options by opponent and return self
+    pub fn opponent_options(mut self, opponent_options: OpponentOptions) -> LichessBot {
+        self.opponent_options = opponent_options;
+
+        self
+    }
+
+    /// set strength limiting and return self
+    pub fn handicap(mut self, handicap: Handicap) -> LichessBot {
+        self.handicap = handicap;
+
+        self
+    }
+
+    /// uci options of game, the uci options of the bot overridden by the options of the opponent,
+    /// which are chosen by ratings and the stored games against the opponent, then by the
+    /// strength limiting options
+    fn game_uci_options(&self, game: &GameInfo) -> std::collections::HashMap<String, String> {
+        let history = self
+            .store
+            .as_ref()
+            .and_then(|store| store.opponent(&game.opponent().name).ok());
+
+        let mut uci_options = self.uci_options.clone();
+
+        for (name, value) in self.opponent_options.options_for(game, history.as_ref()) {
+            if log_enabled!(Level::Info) {
+                info!(
+                    "uci option {} = {} against {}",
+                    name,
+                    value,
+                    game.opponent().name
+                );
+            }
+
+            uci_options.insert(name, value);
+        }
+
+        for (name, value) in self.handicap.uci_options(game) {
+            if log_enabled!(Level::Info) {
+                info!(
+                    "limiting strength with {} = {} against {}",
+                    name,
+                    value,
+                    game.opponent().name
+                );
+            }
+
+            uci_options.insert(name, value);
+        }
+
+        uci_options
+    }
+
+    /// create move sources of game in move source order,
+    /// custom move sources are taken from the bot until the game is over
+    fn take_move_sources(&mut self, game: &GameInfo) -> Vec<Box<dyn MoveSource>> {
+        let mut customs = std::mem::take(&mut self.move_sources);
+
+        let mut sources: Vec<Box<dyn MoveSource>> = vec![];
+
+        for name in &self.move_source_order {
+            match name.as_str() {
+                "repertoire" => sources.push(Box::new(RepertoireMoveSource::new(
+                    std::sync::Arc::new(self.repertoire.clone()),
+                ))),
+                "tablebase" => {
+                    if let Some(tablebase) = &self.tablebase {
+                        sources.push(Box::new(TablebaseMoveSource::new(tablebase.clone())));
+                    }
+                }
+                "book" => sources.push(Box::new(
+                    BookMoveSource::new(self.books.clone())
+                        .verifier(self.book_verifier())
+                        .seed(self.book_seed),
+                )),
+                "engine" => match &self.engine_name {
+                    Some(engine_name) => sources.push(Box::new(
+                        EngineMoveSource::new(
+                            engine_name,
+                            self.game_uci_options(game),
+                            self.state.clone(),
+                        )
+                        .backup_engine_name(self.backup_engine_name.to_owned())
+                        .max_restarts(self.max_engine_restarts)
+                        .pick(self.handicap.move_pick(game)),
+                    )),
+                    _ => {
+                        if log_enabled!(Level::Debug) {
+                            debug!("no engine available for playing game");
+                        }
+                    }
+                },
+                "search" => sources.push(Box::new(SearchMoveSource::new(self.search_depth))),
+                "random" => sources.push(Box::new(RandomMoveSource)),
+                _ => match customs.iter().position(|source| &source.name() == name) {
+                    Some(index) => sources.push(customs.remove(index)),
+                    _ => {
+                        if log_enabled!(Level::Info) {
+                            info!("unknown move source {}", name);
+                        }
+                    }
+                },
+            }
+        }
+
+        self.move_sources = customs;
+
+        sources
+    }
+
+    /// give custom move sources of a game back to the bot
+    fn return_move_sources(&mut self, sources: Vec<Box<dyn MoveSource>>) {
+        for source in sources {
+            if !BUILT_IN_MOVE_SOURCES.contains(&source.name().as_str()) {
+                self.move_sources.push(source);
+            }
+        }
+    }
+
+    /// submit move and record failed attempts as incidents
+    async fn submit_move(
+        &self,
+        game_id: &str,
+        submission: &mut Submission,
+        record: &mut GameRecord,
+    ) {
+        submission
+            .submit(
+                &self.lichess,
+                game_id,
+                submission.offering_draw,
+                self.max_move_attempts,
+            )
+            .await;
+
+        let ply = submission.moves.split_whitespace().count();
+
+        for err in submission.take_errors() {
+            let (kind, message) = match err {
+                SubmitError::Transient(message, _) => (IncidentKind::MoveRetry, message),
+                SubmitError::Permanent(message) => (IncidentKind::MoveRejected, message),
+            };
+
+            record.incident(Incident::new(
+                ply,
+                "lichess",
+                kind,
+                format!("move {} : {}", submission.uci, message),
+            ));
+        }
+    }
+
     /// play game
     async fn play_game(&mut self, game_id: String) -> Result<(), Box<dyn std::error::Error>> {
         if log_enabled!(Level::Info) {
@@ -228,103 +1056,205 @@ impl LichessBot {
             }
         });
 
-        let mut game_stream = self.lichess.stream_bot_game_state(&game_id).await.unwrap();
+        // the lichess client cannot parse game states with takeback proposals
+        let mut game_stream = self.bot_api.stream_game(&game_id).await?;
 
-        let mut bot_white = true;
+        let mut record: Option<GameRecord> = None;
 
-        let engine: Option<std::sync::Arc<uciengine::uciengine::UciEngine>> =
-            match self.engine_name.to_owned() {
-                Some(engine_name) => {
-                    if log_enabled!(Level::Debug) {
-                        debug!("created engine for playing game");
-                    }
+        let mut sources: Vec<Box<dyn MoveSource>> = vec![];
 
-                    Some(UciEngine::new(engine_name))
-                }
-                _ => {
-                    if log_enabled!(Level::Debug) {
-                        debug!("no engine available for playing game");
-                    }
+        let mut submission: Option<Submission> = None;
 
-                    None
+        let mut chat: Option<GameChat> = None;
+
+        let mut resign = GameResign::new(self.resign.clone());
+
+        let mut draw = GameDraw::new(self.draw.clone());
+
+        // a takeback proposal stays in the game states until it is answered
+        let mut takeback_answered = false;
+
+        loop {
+            // while a submitted move is not confirmed, wait for the next game state only so long
+            let next_event = match submission.as_ref().map(|pending| pending.confirm_wait()) {
+                Some(wait) => {
+                    match tokio::time::timeout(
+                        tokio::time::Duration::from_millis(wait as u64),
+                        game_stream.next_event(),
+                    )
+                    .await
+                    {
+                        Ok(next_event) => next_event?,
+                        Err(_) => {
+                            if let (Some(pending), Some(record)) = (&mut submission, &mut record) {
+                                record.incident(Incident::new(
+                                    pending.moves.split_whitespace().count(),
+                                    "lichess",
+                                    IncidentKind::MoveUnconfirmed,
+                                    format!("move {} not confirmed", pending.uci),
+                                ));
+
+                                if pending.resubmissions < self.max_move_attempts {
+                                    pending.resubmissions += 1;
+
+                                    self.submit_move(&game_id, pending, record).await;
+                                }
+
+                                if pending.rejected
+                                    || (pending.resubmissions >= self.max_move_attempts)
+                                {
+                                    submission = None;
+                                }
+                            } else {
+                                submission = None;
+                            }
+
+                            continue;
+                        }
+                    }
                 }
+                _ => game_stream.next_event().await?,
             };
 
-        let mut ponder: Option<String> = None;
+            let game_event = match next_event {
+                Some(game_event) => game_event,
+                _ => break,
+            };
 
-        while let Some(game_event) = game_stream.try_next().await? {
             if log_enabled!(Level::Debug) {
                 debug!("game event {:?}", game_event);
             }
 
-            let white: String;
-            let black: String;
+            let (wtakeback, btakeback) = (game_event.wtakeback, game_event.btakeback);
 
-            let state_opt = match game_event {
+            let state_opt = match game_event.event {
                 BoardState::GameFull(game_full) => {
                     if log_enabled!(Level::Debug) {
                         debug!("game full {:?}", game_full);
                     }
 
-                    white = match game_full.white {
-                        LightUser(user) => user.username,
-                        StockFish(sf) => format!("Stockfish AI level {}", sf.ai_level),
-                    };
-
-                    black = match game_full.black {
-                        LightUser(user) => user.username,
-                        StockFish(sf) => format!("Stockfish AI level {}", sf.ai_level),
-                    };
-
-                    if self.bot_name == black {
-                        bot_white = false;
-                    }
+                    let info = GameInfo::from_game_full(&game_full, &self.bot_name);
 
                     if log_enabled!(Level::Info) {
                         info!(
                             "**************\n{} - {} ( bot playing white {} )\n**************",
-                            white, black, bot_white
+                            info.white.name, info.black.name, info.bot_white
+                        );
+                    }
+
+                    if record.is_none() {
+                        sources = self.take_move_sources(&info);
+
+                        let mut new_record = GameRecord::new(info.clone());
+
+                        for source in sources.iter_mut() {
+                            source.start_game(&info).await;
+
+                            for incident in source.take_incidents() {
+                                new_record.incident(incident);
+                            }
+                        }
+
+                        let game_chat = GameChat::new(
+                            self.chat.clone(),
+                            &self.bot_name,
+                            self.chat_engine_name(),
+                        )
+                        .books(
+                            self.books
+                                .snapshot()
+                                .iter()
+                                .filter(|book| book.scope.matches(&info))
+                                .map(|book| book.name.to_owned())
+                                .collect(),
                         );
+
+                        if game_full.state.status == "started" {
+                            self.write_chat(&game_id, game_chat.greeting(&new_record));
+                        }
+
+                        chat = Some(game_chat);
+
+                        record = Some(new_record);
+                    }
+
+                    if let Some(record) = &mut record {
+                        record.update(&game_full.state);
                     }
 
                     Some(game_full.state)
                 }
                 BoardState::GameState(game_state) => {
-                    match game_state.status.as_str() {
-                        "aborted" => {
-                            // just an info, no processing is needed
-                            if log_enabled!(Level::Info) {
-                                info!(
-                                    "game info, no processing needed, status {:?}",
-                                    game_state.status
-                                );
+                    if let Some(record) = &mut record {
+                        if let Some(plies) = taken_back(&record.moves, &game_state.moves) {
+                            record.incident(Incident::new(
+                                game_state.moves.split_whitespace().count(),
+                                "lichess",
+                                IncidentKind::Takeback,
+                                format!("{} plies taken back", plies),
+                            ));
+
+                            // evals and offers of the taken back moves no longer count
+                            submission = None;
+                            resign = GameResign::new(self.resign.clone());
+                            draw = GameDraw::new(self.draw.clone());
+
+                            if let Some(chat) = &mut chat {
+                                chat.last_move = None;
                             }
-
-                            None
                         }
-                        _ => Some(game_state),
+
+                        record.update(&game_state);
                     }
+
+                    Some(game_state)
                 }
-                _ => {
-                    if log_enabled!(Level::Debug) {
-                        debug!("undhandled game event {:?}", game_event);
+                BoardState::ChatLine(line) => {
+                    if log_enabled!(Level::Info) {
+                        info!("chat {} in {} : {}", line.username, line.room, line.text);
+                    }
+
+                    if let Some(answer) = chat
+                        .as_mut()
+                        .and_then(|chat| chat.answer(&line.username, &line.text, &line.room))
+                    {
+                        self.write_chat(&game_id, vec![answer]);
                     }
 
                     None
                 }
             };
 
-            if state_opt.is_some() {
-                let state = state_opt.unwrap();
+            if let (Some(_), Some(record)) = (&state_opt, &record) {
+                let opponent_takeback = match record.info.bot_white {
+                    true => btakeback,
+                    _ => wtakeback,
+                };
+
+                if opponent_takeback && !takeback_answered {
+                    self.answer_takeback(&game_id, &record.info).await;
+                }
+
+                takeback_answered = opponent_takeback;
+            }
+
+            if let Some(outcome) = record.as_ref().and_then(|record| record.outcome()) {
+                if log_enabled!(Level::Info) {
+                    info!("game {} over {:?}", game_id, outcome);
+                }
+
+                // no more moves are needed, the move sources stop engine and ponder right away
+                break;
+            }
 
+            if let (Some(state), Some(record)) = (state_opt, &mut record) {
                 if log_enabled!(Level::Debug) {
                     debug!("game state {:?}", state);
                 }
 
                 let (fen, epd) = make_uci_moves(state.moves.as_str())?;
 
-                let _ = self
-                    .set_state(self.get_state().await.set_current_fen(Some(fen.to_owned())))
+                self.set_state(self.get_state().await.set_current_fen(Some(fen.to_owned())))
                     .await;
 
                 if log_enabled!(Level::Debug) {
@@ -334,290 +1264,175 @@ impl LichessBot {
                 let setup: Fen = fen.parse()?;
                 let pos: Chess = setup.position(shakmaty::CastlingMode::Standard)?;
 
-                let legals = pos.legals();
-
-                if legals.len() > 0 {
-                    let mut move_source = "random";
+                let req = MoveRequest {
+                    game: record.info.clone(),
+                    moves: state.moves.to_owned(),
+                    pos,
+                    fen,
+                    epd,
+                    clock: Clock::from_state(&state),
+                    status: state.status.to_owned(),
+                };
+
+                for source in sources.iter_mut() {
+                    source.update(&req).await;
+                }
 
-                    let rand_move = legals.choose(&mut rand::thread_rng()).unwrap();
+                let mut move_pending = false;
 
-                    let rand_uci = Uci::from_standard(&rand_move).to_string();
+                if let Some(pending) = &submission {
+                    if pending.confirmed_by(&req.moves) {
+                        if log_enabled!(Level::Info) {
+                            info!("move {} confirmed", pending.uci);
+                        }
 
-                    if log_enabled!(Level::Debug) {
-                        debug!("rand uci {}", rand_uci);
+                        submission = None;
+                    } else if pending.moves == req.moves {
+                        // the move may still be on its way, it is resubmitted if not confirmed in time
+                        move_pending = true;
+                    } else {
+                        submission = None;
                     }
+                }
 
-                    let turn = setup.turn;
-
+                if req.pos.legals().is_empty() {
+                    if log_enabled!(Level::Info) {
+                        info!("position has no legal move");
+                    }
+                } else {
                     if log_enabled!(Level::Debug) {
-                        debug!("turn {:?}", turn);
+                        debug!("turn {:?}", req.pos.turn());
                     }
 
-                    let bot_turn = ((turn == Color::White) && bot_white)
-                        || ((turn == Color::Black) && !bot_white);
+                    let bot_turn = req.bot_turn();
 
                     if log_enabled!(Level::Debug) {
                         debug!("bot turn {}", bot_turn);
                     }
 
-                    if bot_turn {
-                        let mut bestmove = rand_uci;
-
-                        let pos = self.book.positions.get(&epd);
-
-                        let mut has_book_move = false;
-
-                        if let Some(pos) = pos {
-                            let mixed: usize = env_or("RUST_BOT_MIXED", 90);
-
-                            if log_enabled!(Level::Info) {
-                                info!("searching for random move by mixed {}", mixed);
-                            }
-
-                            if let Some(m) = pos.get_random_mixed(mixed) {
-                                bestmove = m.uci.to_owned();
-
-                                has_book_move = true;
-
-                                move_source = "book";
-
-                                if log_enabled!(Level::Info) {
-                                    info!("book move found {}", bestmove);
-                                }
-                            }
-                        }
-
-                        let id = game_id.to_owned();
-
-                        if engine.is_some() && (!has_book_move) {
-                            let _ = self
-                                .set_state(self.get_state().await.set_engine_thinking(true))
-                                .await;
-
-                            let moves = format!("{}", state.moves);
-
-                            let go_job = GoJob::new()
-                                .uci_opt("UCI_Variant", "chess")
-                                .pos_startpos()
-                                .pos_moves(moves)
-                                .tc(Timecontrol {
-                                    wtime: state.wtime as usize,
-                                    winc: state.winc as usize,
-                                    btime: state.btime as usize,
-                                    binc: state.binc as usize,
-                                });
-
-                            let mut ponderhit = false;
-                            let mut pondermiss = false;
-
-                            if (state.moves.len() > 0) && (ponder.is_some()) {
-                                // check ponder
-                                let mut moves_array: Vec<&str> = state.moves.split(" ").collect();
-
-                                let last_uci = moves_array.pop().unwrap();
+                    if bot_turn && !move_pending {
+                        let start = std::time::Instant::now();
 
+                        if let Some(proposal) = select_move(&mut sources, &req, record).await {
+                            if resign.update(&record.info, proposal.score.as_ref()) {
                                 if log_enabled!(Level::Info) {
-                                    info!("incoming uci {}", last_uci);
+                                    info!(
+                                        "resigning game {} after {} moves with eval {:?}",
+                                        game_id, resign.low_moves, proposal.score
+                                    );
                                 }
 
-                                ponderhit = match ponder {
-                                    Some(ref uci) => {
-                                        if log_enabled!(Level::Info) {
-                                            info!("expected uci {}", uci);
-                                        }
-
-                                        last_uci == uci
-                                    }
-                                    _ => false,
-                                };
+                                self.resign_game(&game_id).await;
 
-                                pondermiss = !ponderhit;
+                                continue;
                             }
 
-                            let elapsed: u128;
-
-                            let go_result = match ponderhit || pondermiss {
-                                true => {
-                                    if log_enabled!(Level::Info) {
-                                        info!("ponderhit {} pondermiss {}", ponderhit, pondermiss);
-                                    }
-
-                                    if pondermiss {
-                                        if log_enabled!(Level::Info) {
-                                            info!("pondermiss, stopping engine");
-                                        }
-
-                                        let start = std::time::Instant::now();
-
-                                        let _ = engine
-                                            .clone()
-                                            .unwrap()
-                                            .go(GoJob::new().pondermiss())
-                                            .await;
-
-                                        if log_enabled!(Level::Info) {
-                                            info!(
-                                                "engine start from scratch thinking on {:?}",
-                                                go_job
-                                            );
-                                        }
-
-                                        let result = engine.clone().unwrap().go(go_job).await;
-
-                                        elapsed = start.elapsed().as_millis();
-
-                                        result
-                                    } else {
-                                        if log_enabled!(Level::Info) {
-                                            info!("ponderhit, waiting for result");
-                                        }
-
-                                        let start = std::time::Instant::now();
-
-                                        let result = engine
-                                            .clone()
-                                            .unwrap()
-                                            .go(GoJob::new().ponderhit())
-                                            .await;
-
-                                        elapsed = start.elapsed().as_millis();
-
-                                        result
-                                    }
-                                }
-                                _ => {
-                                    if log_enabled!(Level::Info) {
-                                        info!("engine start thinking on {:?}", go_job);
-                                    }
-
-                                    let mut go_job = go_job;
-
-                                    for (key, value) in &self.uci_options {
-                                        if log_enabled!(Level::Info) {
-                                            info!("adding uci option {} = {}", key, value);
-                                        }
+                            for source in sources.iter_mut() {
+                                source.move_played(&req, &proposal).await;
+                            }
 
-                                        go_job = go_job.uci_opt(key, value);
-                                    }
+                            if let Some(chat) = &mut chat {
+                                chat.move_played(&proposal);
+                            }
 
-                                    if log_enabled!(Level::Debug) {
-                                        debug!("mounted go job {:?}", go_job);
-                                    }
+                            record.decision(
+                                proposal.decision(req.ply(), start.elapsed().as_millis() as usize),
+                            );
 
-                                    let start = std::time::Instant::now();
+                            if log_enabled!(Level::Info) {
+                                info!(
+                                    "making move {} , source '{}'",
+                                    proposal.uci, proposal.source
+                                );
+                            }
 
-                                    let result = engine.clone().unwrap().go(go_job).await;
+                            let tablebase_draw = self
+                                .tablebase
+                                .as_ref()
+                                .and_then(|tablebase| tablebase.is_draw(&req.pos));
 
-                                    elapsed = start.elapsed().as_millis();
+                            let draw_fine =
+                                draw.update(req.ply(), proposal.score.as_ref(), tablebase_draw);
 
-                                    result
-                                }
+                            let opponent_offers = match record.info.bot_white {
+                                true => state.bdraw,
+                                _ => state.wdraw,
                             };
 
-                            if log_enabled!(Level::Debug) {
-                                debug!("thinking took {} ms , result {:?}", elapsed, go_result);
-                            }
-
-                            move_source = "engine";
+                            let offering_draw =
+                                draw.offering_draw(req.ply(), draw_fine, opponent_offers);
 
-                            let mut eff_wtime = state.wtime as i32;
-                            let mut eff_btime = state.btime as i32;
-
-                            if bot_white {
-                                eff_wtime -= elapsed as i32;
+                            if log_enabled!(Level::Info) && (opponent_offers || offering_draw) {
+                                info!(
+                                    "draw offered by opponent {} , offering or accepting {}",
+                                    opponent_offers, offering_draw
+                                );
+                            }
 
-                                if eff_wtime < 0 {
-                                    eff_wtime = 100;
-                                }
+                            // the time spent choosing the move is gone from the clock
+                            let budget =
+                                submit_budget(req.bot_time(), start.elapsed().as_millis() as usize);
 
-                                if log_enabled!(Level::Info) {
-                                    info!("changing wtime from {} to {}", state.wtime, eff_wtime);
-                                }
-                            } else {
-                                eff_btime -= elapsed as i32;
+                            let mut pending = Submission::new(&proposal.uci, &req.moves, budget)
+                                .offering_draw(offering_draw);
 
-                                if eff_btime < 0 {
-                                    eff_btime = 100;
-                                }
+                            self.submit_move(&game_id, &mut pending, record).await;
 
-                                if log_enabled!(Level::Info) {
-                                    info!("changing btime from {} to {}", state.btime, eff_btime);
-                                }
+                            if !pending.rejected {
+                                submission = Some(pending);
                             }
+                        }
+                    }
+                }
+            }
+        }
 
-                            if let Ok(go_result) = go_result {
-                                if let Some(bm) = go_result.bestmove {
-                                    bestmove = bm;
-                                }
-
-                                if log_enabled!(Level::Debug) {
-                                    debug!("ponder before {:?}", ponder);
-                                }
+        for source in sources.iter_mut() {
+            source.end_game().await;
+        }
 
-                                ponder = go_result.ponder;
+        self.return_move_sources(sources);
 
-                                if log_enabled!(Level::Info) {
-                                    info!("set ponder to {:?}", ponder);
-                                }
+        if let Some(record) = record {
+            if let Some(outcome) = record.outcome() {
+                if let Some(result) = outcome.result {
+                    let books = self.books.snapshot();
+                    let info = record.info.clone();
+                    let moves = record.moves.to_owned();
 
-                                if let Some(ref uci) = ponder {
-                                    let new_moves = match state.moves.as_str() {
-                                        "" => format!("{} {}", bestmove, uci),
-                                        _ => format!("{} {} {}", state.moves, bestmove, uci),
-                                    };
-
-                                    if log_enabled!(Level::Info) {
-                                        info!("start pondering on {}", new_moves);
-                                    }
-
-                                    let go_job_ponder = GoJob::new()
-                                        .uci_opt("UCI_Variant", "chess")
-                                        .pos_startpos()
-                                        .pos_moves(new_moves)
-                                        .ponder()
-                                        .tc(Timecontrol {
-                                            wtime: eff_wtime as usize,
-                                            winc: state.winc as usize,
-                                            btime: eff_btime as usize,
-                                            binc: state.binc as usize,
-                                        });
-
-                                    let _ = engine.clone().unwrap().go(go_job_ponder);
-                                }
-                            }
+                    // learning books are saved with blocking file io
+                    let _ = tokio::task::spawn_blocking(move || {
+                        for book in books {
+                            book.learn(&info, &moves, result.score());
                         }
+                    })
+                    .await;
 
-                        let _ = self
-                            .set_state(self.get_state().await.set_engine_thinking(false))
-                            .await;
+                    if let Some(chat) = &chat {
+                        self.write_chat(&game_id, chat.goodbye(&record));
+                    }
+                }
 
-                        if log_enabled!(Level::Info) {
-                            info!("making move {} , source '{}'", bestmove, move_source);
-                        }
+                self.set_state(self.get_state().await.add_outcome(&outcome))
+                    .await;
 
-                        let result = self
-                            .lichess
-                            .make_a_bot_move(id.as_str(), bestmove.as_str(), false)
-                            .await;
+                self.save_game(&record, &outcome).await;
 
-                        if log_enabled!(Level::Debug) {
-                            debug!("make move result {:?}", result);
-                        }
-                    }
-                } else {
-                    if log_enabled!(Level::Info) {
-                        info!("position has no legal move");
-                    }
+                self.analyze_game(&record, &outcome);
+
+                for hook in self.game_end_hooks.iter() {
+                    hook(&record, &outcome);
                 }
             }
-        }
-
-        if engine.is_some() {
-            // stop engine before quitting
-            let _ = engine.clone().unwrap().go(GoJob::new().custom("stop"));
 
-            // quit engine
-            engine.unwrap().quit();
+            if log_enabled!(Level::Info) {
+                info!(
+                    "game {} over, {} incident(s) {:?}",
+                    record.info.id,
+                    record.incidents.len(),
+                    record.incidents
+                );
+            }
         }
 
         Ok(())
@@ -658,78 +1473,89 @@ impl LichessBot {
                 if challenge.speed == "correspondence" {
                     challenge_ok = false;
 
-                    decline_reasons.push(format!("{}", "wrong speed ( correspondence )"));
+                    decline_reasons.push("wrong speed ( correspondence )".to_string());
 
                     reason = "timeControl";
                 }
 
-                if challenge.speed == "classical" {
-                    if !self.enable_classical {
-                        challenge_ok = false;
+                if challenge.speed == "classical" && !self.enable_classical {
+                    challenge_ok = false;
 
-                        decline_reasons.push(format!("{}", "wrong speed ( classical )"));
+                    decline_reasons.push("wrong speed ( classical )".to_string());
 
-                        reason = "timeControl";
-                    }
+                    reason = "timeControl";
                 }
 
-                if challenge.speed == "rapid" {
-                    if !self.enable_rapid {
-                        challenge_ok = false;
+                if challenge.speed == "rapid" && !self.enable_rapid {
+                    challenge_ok = false;
 
-                        decline_reasons.push(format!("{}", "wrong speed ( rapid )"));
+                    decline_reasons.push("wrong speed ( rapid )".to_string());
 
-                        reason = "timeControl";
-                    }
+                    reason = "timeControl";
                 }
 
-                if challenge.speed == "blitz" {
-                    if self.disable_blitz {
-                        challenge_ok = false;
+                if challenge.speed == "blitz" && self.disable_blitz {
+                    challenge_ok = false;
 
-                        decline_reasons.push(format!("{}", "wrong speed ( blitz )"));
+                    decline_reasons.push("wrong speed ( blitz )".to_string());
 
-                        reason = "timeControl";
-                    }
+                    reason = "timeControl";
                 }
 
-                if challenge.speed == "bullet" {
-                    if self.disable_bullet {
-                        challenge_ok = false;
+                if challenge.speed == "bullet" && self.disable_bullet {
+                    challenge_ok = false;
 
-                        decline_reasons.push(format!("{}", "wrong speed ( bullet )"));
+                    decline_reasons.push("wrong speed ( bullet )".to_string());
 
-                        reason = "timeControl";
-                    }
+                    reason = "timeControl";
                 }
 
-                if challenge.speed == "ultrabullet" {
-                    if !self.enable_ultrabullet {
-                        challenge_ok = false;
+                if challenge.speed == "ultrabullet" && !self.enable_ultrabullet {
+                    challenge_ok = false;
 
-                        decline_reasons.push(format!("{}", "wrong speed ( ultrabullet )"));
+                    decline_reasons.push("wrong speed ( ultrabullet )".to_string());
 
-                        reason = "timeControl";
-                    }
+                    reason = "timeControl";
                 }
 
-                if challenge.rated {
-                    if self.disable_rated {
-                        challenge_ok = false;
+                if challenge.rated && self.disable_rated {
+                    challenge_ok = false;
 
-                        decline_reasons.push(format!("{}", "wrong mode ( rated )"));
+                    decline_reasons.push("wrong mode ( rated )".to_string());
 
-                        reason = "casual";
-                    }
+                    reason = "casual";
                 }
 
-                if !challenge.rated {
-                    if !self.enable_casual {
-                        challenge_ok = false;
+                if !challenge.rated && !self.enable_casual {
+                    challenge_ok = false;
+
+                    decline_reasons.push("wrong mode ( casual )".to_string());
+
+                    reason = "rated";
+                }
 
-                        decline_reasons.push(format!("{}", "wrong mode ( casual )"));
+                if let Some(store) = &self.store {
+                    let challenge_decision = ChallengeDecision {
+                        id: challenge.id.to_owned(),
+                        challenger: challenge
+                            .challenger
+                            .as_ref()
+                            .map(|user| user.username.to_owned()),
+                        challenger_rating: challenge
+                            .challenger
+                            .as_ref()
+                            .and_then(|user| user.rating),
+                        speed: challenge.speed.to_owned(),
+                        variant: challenge.variant.key.to_owned(),
+                        rated: challenge.rated,
+                        accepted: challenge_ok,
+                        reasons: decline_reasons.clone(),
+                    };
 
-                        reason = "rated";
+                    if let Err(err) = store.add_challenge(&challenge_decision) {
+                        if log_enabled!(Level::Info) {
+                            info!("storing challenge {} failed {}", challenge.id, err);
+                        }
                     }
                 }
 
@@ -747,7 +1573,7 @@ impl LichessBot {
                         );
                     }
 
-                    let challenge_id = format!("{}", challenge.id);
+                    let challenge_id = challenge.id.to_string();
 
                     tokio::spawn(async move {
                         if log_enabled!(Level::Info) {
@@ -767,7 +1593,7 @@ impl LichessBot {
                 }
             }
             Event::GameStart { game } => {
-                let game_id = format!("{}", game.id);
+                let game_id = game.id.to_string();
 
                 if log_enabled!(Level::Info) {
                     info!("game started {}", game_id);
@@ -800,7 +1626,8 @@ impl LichessBot {
         Ok(())
     }
 
-    /// stream
+    /// stream, returns a sender of control commands and a receiver of the stop result,
+    /// RELOAD_BOOKS_COMMAND reloads the books, any other command stops the stream
     pub async fn stream(
         &'static mut self,
     ) -> (
@@ -814,25 +1641,53 @@ impl LichessBot {
         let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(1);
         let (txa, rxa) = tokio::sync::mpsc::channel::<String>(1);
 
+        let books = self.books.clone();
+
+        if self.book_watch_interval > 0 {
+            tokio::spawn(
+                books
+                    .clone()
+                    .watch(tokio::time::Duration::from_secs(self.book_watch_interval)),
+            );
+        }
+
         tokio::spawn(async move {
-            let result = tokio::select! {
-                res = self.stream_task() => {
-                    let result = format!("stream stopped on its own [{:?}]", res);
+            let stream_task = self.stream_task();
 
-                    if log_enabled!(Level::Info){
-                        info!("{}", result);
-                    }
+            tokio::pin!(stream_task);
 
-                    result
-                },
-                res = rx.recv() => {
-                    let result = format!("stream forced to stop [{:?}]", res);
+            let result = loop {
+                tokio::select! {
+                    res = &mut stream_task => {
+                        let result = format!("stream stopped on its own [{:?}]", res);
 
-                    if log_enabled!(Level::Info){
-                        info!("{}", result);
-                    }
+                        if log_enabled!(Level::Info){
+                            info!("{}", result);
+                        }
+
+                        break result;
+                    },
+                    res = rx.recv() => {
+                        if res.as_deref() == Some(RELOAD_BOOKS_COMMAND) {
+                            if log_enabled!(Level::Info){
+                                info!("reloading books");
+                            }
+
+                            let books = books.clone();
 
-                    result
+                            tokio::spawn(async move { books.reload_in_background(false).await });
+
+                            continue;
+                        }
+
+                        let result = format!("stream forced to stop [{:?}]", res);
+
+                        if log_enabled!(Level::Info){
+                            info!("{}", result);
+                        }
+
+                        break result;
+                    }
                 }
             };
 
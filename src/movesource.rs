@@ -0,0 +1,1079 @@
+use log::{debug, info, log_enabled, Level};
+
+use async_trait::async_trait;
+
+use shakmaty::uci::Uci;
+use shakmaty::{Chess, Color, Position, Setup};
+
+use rand::prelude::*;
+
+use uciengine::analysis::{AnalysisInfo, Score};
+use uciengine::uciengine::*;
+
+use crate::book::*;
+use crate::game::*;
+use crate::lichessbot::BotState;
+use crate::opponent::{MovePick, PvLine};
+use crate::ponder::*;
+use crate::repertoire::*;
+use crate::search::*;
+use crate::tablebase::*;
+
+/// request for a move
+#[derive(Debug, Clone)]
+pub struct MoveRequest {
+    /// game info
+    pub game: GameInfo,
+    /// game moves so far, space separated uci
+    pub moves: String,
+    /// current position
+    pub pos: Chess,
+    /// fen of current position
+    pub fen: String,
+    /// epd of current position
+    pub epd: String,
+    /// clock
+    pub clock: Clock,
+    /// game status
+    pub status: String,
+}
+
+/// implementation of move request
+impl MoveRequest {
+    /// true if the bot is to move
+    pub fn bot_turn(&self) -> bool {
+        (self.pos.turn() == Color::White) == self.game.bot_white
+    }
+
+    /// legal moves of position in uci notation
+    pub fn legal_ucis(&self) -> Vec<String> {
+        self.pos
+            .legals()
+            .iter()
+            .map(|m| Uci::from_standard(m).to_string())
+            .collect()
+    }
+
+    /// true if uci is a legal move in position
+    pub fn is_legal<T>(&self, uci: T) -> bool
+    where
+        T: core::fmt::Display,
+    {
+        match uci.to_string().parse::<Uci>() {
+            Ok(uci) => uci.to_move(&self.pos).is_ok(),
+            _ => false,
+        }
+    }
+
+    /// true if uci is a legal move in position and reply is a legal move after it
+    pub fn is_legal_reply<T, R>(&self, uci: T, reply: R) -> bool
+    where
+        T: core::fmt::Display,
+        R: core::fmt::Display,
+    {
+        let m = match uci.to_string().parse::<Uci>() {
+            Ok(uci) => match uci.to_move(&self.pos) {
+                Ok(m) => m,
+                _ => return false,
+            },
+            _ => return false,
+        };
+
+        let mut pos = self.pos.clone();
+
+        pos.play_unchecked(&m);
+
+        match reply.to_string().parse::<Uci>() {
+            Ok(reply) => reply.to_move(&pos).is_ok(),
+            _ => false,
+        }
+    }
+
+    /// time left for the bot, in milliseconds
+    pub fn bot_time(&self) -> usize {
+        self.clock.time(self.game.bot_white)
+    }
+
+    /// number of moves played
+    pub fn ply(&self) -> usize {
+        self.moves.split_whitespace().count()
+    }
+}
+
+/// move proposed by a move source
+#[derive(Debug, Clone)]
+pub struct MoveProposal {
+    /// move in uci notation
+    pub uci: String,
+    /// name of move source
+    pub source: String,
+    /// evaluation from the point of view of the bot
+    pub score: Option<Score>,
+    /// search depth
+    pub depth: Option<usize>,
+    /// principal variation, space separated uci
+    pub pv: Option<String>,
+    /// expected opponent reply
+    pub ponder: Option<String>,
+}
+
+/// implementation of move proposal
+impl MoveProposal {
+    /// create move proposal
+    pub fn new<U, S>(uci: U, source: S) -> MoveProposal
+    where
+        U: core::fmt::Display,
+        S: core::fmt::Display,
+    {
+        MoveProposal {
+            uci: uci.to_string(),
+            source: source.to_string(),
+            score: None,
+            depth: None,
+            pv: None,
+            ponder: None,
+        }
+    }
+
+    /// decision of playing the proposal at ply after thinking time in milliseconds
+    pub fn decision(&self, ply: usize, time: usize) -> MoveDecision {
+        MoveDecision {
+            ply,
+            uci: self.uci.to_owned(),
+            source: self.source.to_owned(),
+            score: self.score,
+            depth: self.depth,
+            time,
+        }
+    }
+
+    /// set score and return self
+    pub fn score(mut self, score: Score) -> MoveProposal {
+        self.score = Some(score);
+
+        self
+    }
+
+    /// set depth and return self
+    pub fn depth(mut self, depth: usize) -> MoveProposal {
+        self.depth = Some(depth);
+
+        self
+    }
+
+    /// set pv and return self
+    pub fn pv<T>(mut self, pv: T) -> MoveProposal
+    where
+        T: core::fmt::Display,
+    {
+        self.pv = Some(pv.to_string());
+
+        self
+    }
+
+    /// set ponder and return self
+    pub fn ponder<T>(mut self, ponder: T) -> MoveProposal
+    where
+        T: core::fmt::Display,
+    {
+        self.ponder = Some(ponder.to_string());
+
+        self
+    }
+}
+
+/// source of moves, the bot asks its move sources in order until one proposes a move
+#[async_trait]
+pub trait MoveSource: Send + Sync {
+    /// name of move source, as used in the move source order
+    fn name(&self) -> String;
+
+    /// called when a game starts
+    async fn start_game(&mut self, _game: &GameInfo) {}
+
+    /// called on every game state, also when it is not the bot's turn
+    async fn update(&mut self, _req: &MoveRequest) {}
+
+    /// propose a move, None if the source has no move for the position
+    async fn get_move(&mut self, req: &MoveRequest) -> Option<MoveProposal>;
+
+    /// called with the move that is going to be played, proposed by this or another source
+    async fn move_played(&mut self, _req: &MoveRequest, _proposal: &MoveProposal) {}
+
+    /// incidents since last call, recorded in the game record
+    fn take_incidents(&mut self) -> Vec<Incident> {
+        vec![]
+    }
+
+    /// called when the game is over
+    async fn end_game(&mut self) {}
+}
+
+/// ask move sources in order for a legal move, play random move if none of them has one
+pub async fn select_move(
+    sources: &mut [Box<dyn MoveSource>],
+    req: &MoveRequest,
+    record: &mut GameRecord,
+) -> Option<MoveProposal> {
+    for source in sources.iter_mut() {
+        let proposal = source.get_move(req).await;
+
+        for incident in source.take_incidents() {
+            record.incident(incident);
+        }
+
+        match proposal {
+            Some(proposal) => {
+                if req.is_legal(&proposal.uci) {
+                    return Some(proposal);
+                }
+
+                record.incident(Incident::new(
+                    req.ply(),
+                    source.name(),
+                    IncidentKind::IllegalMove,
+                    format!("illegal move {} in {}", proposal.uci, req.fen),
+                ));
+            }
+            _ => {
+                if log_enabled!(Level::Debug) {
+                    debug!("move source {} has no move", source.name());
+                }
+            }
+        }
+    }
+
+    RandomMoveSource.get_move(req).await
+}
+
+/// names of built in move sources
+pub const BUILT_IN_MOVE_SOURCES: [&str; 6] = [
+    "repertoire",
+    "book",
+    "tablebase",
+    "engine",
+    "search",
+    "random",
+];
+
+/// repertoire move source, plays the repertoire of the bot's color in standard games
+pub struct RepertoireMoveSource {
+    /// repertoire
+    pub repertoire: std::sync::Arc<Repertoire>,
+}
+
+/// implementation of repertoire move source
+impl RepertoireMoveSource {
+    /// create repertoire move source
+    pub fn new(repertoire: std::sync::Arc<Repertoire>) -> RepertoireMoveSource {
+        RepertoireMoveSource { repertoire }
+    }
+}
+
+#[async_trait]
+impl MoveSource for RepertoireMoveSource {
+    fn name(&self) -> String {
+        "repertoire".to_string()
+    }
+
+    async fn get_move(&mut self, req: &MoveRequest) -> Option<MoveProposal> {
+        if (req.game.variant != "standard") || (req.game.initial_fen != "startpos") {
+            return None;
+        }
+
+        let uci = self.repertoire.get_move(req.game.bot_white, &req.moves)?;
+
+        if !req.is_legal(&uci) {
+            return None;
+        }
+
+        if log_enabled!(Level::Info) {
+            info!("repertoire move found {}", uci);
+        }
+
+        Some(MoveProposal::new(uci, self.name()))
+    }
+}
+
+/// book move source, books are asked in order until one of them has a move
+pub struct BookMoveSource {
+    /// books in order of priority
+    pub books: BookList,
+    /// checks book moves with an engine, a rejected move falls through to the next book ( optional )
+    pub verifier: Option<BookVerifier>,
+    /// incidents not yet taken
+    pub incidents: Vec<Incident>,
+    /// random number generator of move selection
+    pub rng: StdRng,
+}
+
+/// implementation of book move source
+impl BookMoveSource {
+    /// create book move source
+    pub fn new(books: BookList) -> BookMoveSource {
+        BookMoveSource {
+            books,
+            verifier: None,
+            incidents: vec![],
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// seed random number generator, making move selection reproducible, and return self
+    pub fn seed(mut self, seed: Option<u64>) -> BookMoveSource {
+        if let Some(seed) = seed {
+            self.rng = StdRng::seed_from_u64(seed);
+        }
+
+        self
+    }
+
+    /// set verifier and return self
+    pub fn verifier(mut self, verifier: Option<BookVerifier>) -> BookMoveSource {
+        self.verifier = verifier;
+
+        self
+    }
+}
+
+#[async_trait]
+impl MoveSource for BookMoveSource {
+    fn name(&self) -> String {
+        "book".to_string()
+    }
+
+    async fn get_move(&mut self, req: &MoveRequest) -> Option<MoveProposal> {
+        for book in self.books.snapshot() {
+            if let Some(uci) = book.get_move(req, &mut self.rng) {
+                if log_enabled!(Level::Info) {
+                    info!("book move found {} in {}", uci, book.name);
+                }
+
+                if let Some(verifier) = &mut self.verifier {
+                    if !verifier.verify(req, &book.name, &uci).await {
+                        self.incidents.push(Incident::new(
+                            req.ply(),
+                            self.name(),
+                            IncidentKind::BookMoveRejected,
+                            format!("book move {} of {} rejected", uci, book.name),
+                        ));
+
+                        continue;
+                    }
+                }
+
+                return Some(MoveProposal::new(uci, self.name()));
+            }
+        }
+
+        None
+    }
+
+    fn take_incidents(&mut self) -> Vec<Incident> {
+        std::mem::take(&mut self.incidents)
+    }
+
+    async fn end_game(&mut self) {
+        if let Some(verifier) = &mut self.verifier {
+            verifier.end_game();
+        }
+    }
+}
+
+/// minimum time left in milliseconds for retrying a search after an engine crash
+pub const ENGINE_RETRY_MIN_TIME: usize = 1000;
+
+/// min time an engine search may take before the engine counts as hung, in milliseconds
+pub const ENGINE_TIMEOUT_MIN: usize = 1000;
+
+/// time after which an engine search of request counts as hung, half of the bot's time,
+/// but at least ENGINE_TIMEOUT_MIN
+pub fn engine_timeout(req: &MoveRequest) -> usize {
+    (req.bot_time() / 2).max(ENGINE_TIMEOUT_MIN)
+}
+
+/// true if the engine executable exists, as a path or as a command found in PATH
+pub fn engine_exists<T>(engine_name: T) -> bool
+where
+    T: core::fmt::Display,
+{
+    let engine_name = engine_name.to_string();
+
+    let path = std::path::Path::new(&engine_name);
+
+    if path.is_file() {
+        return true;
+    }
+
+    if path.components().count() > 1 {
+        return false;
+    }
+
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(path).is_file()))
+        .unwrap_or(false)
+}
+
+/// spawn uci engine, None if the engine executable cannot be started
+pub fn spawn_engine<T>(engine_name: T) -> Option<std::sync::Arc<UciEngine>>
+where
+    T: core::fmt::Display,
+{
+    let engine_name = engine_name.to_string();
+
+    if !engine_exists(&engine_name) {
+        if log_enabled!(Level::Info) {
+            info!("engine {} not found", engine_name);
+        }
+
+        return None;
+    }
+
+    // UciEngine::new panics if the process cannot be spawned for other reasons, like missing
+    // permissions, the panic message is still printed by the panic hook
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        UciEngine::new(&engine_name)
+    }))
+    .ok()
+}
+
+/// uci engine move source, the engine process lives for the duration of a game
+pub struct EngineMoveSource {
+    /// engine executable name
+    pub engine_name: String,
+    /// backup engine executable name, used when the engine keeps crashing
+    pub backup_engine_name: Option<String>,
+    /// number of restarts of the engine in a game before switching to the backup engine
+    pub max_restarts: usize,
+    /// uci options
+    pub uci_options: std::collections::HashMap<String, String>,
+    /// engine of current game
+    pub engine: Option<std::sync::Arc<UciEngine>>,
+    /// restarts in current game
+    pub restarts: usize,
+    /// backup engine is in use
+    pub using_backup: bool,
+    /// ponder state of current game
+    pub ponder: Ponder,
+    /// incidents not yet taken
+    pub incidents: Vec<Incident>,
+    /// bot state, to report engine thinking
+    pub state: std::sync::Arc<tokio::sync::Mutex<BotState>>,
+    /// pick among multi pv lines instead of playing the best move ( optional )
+    pub pick: Option<MovePick>,
+}
+
+/// implementation of engine move source
+impl EngineMoveSource {
+    /// create engine move source
+    pub fn new<T>(
+        engine_name: T,
+        uci_options: std::collections::HashMap<String, String>,
+        state: std::sync::Arc<tokio::sync::Mutex<BotState>>,
+    ) -> EngineMoveSource
+    where
+        T: core::fmt::Display,
+    {
+        EngineMoveSource {
+            engine_name: engine_name.to_string(),
+            backup_engine_name: None,
+            max_restarts: 2,
+            uci_options,
+            engine: None,
+            restarts: 0,
+            using_backup: false,
+            ponder: Ponder::new(),
+            incidents: vec![],
+            state,
+            pick: None,
+        }
+    }
+
+    /// set backup engine name and return self
+    pub fn backup_engine_name(mut self, backup_engine_name: Option<String>) -> EngineMoveSource {
+        self.backup_engine_name = backup_engine_name;
+
+        self
+    }
+
+    /// set max restarts and return self
+    pub fn max_restarts(mut self, max_restarts: usize) -> EngineMoveSource {
+        self.max_restarts = max_restarts;
+
+        self
+    }
+
+    /// set pick among multi pv lines and return self
+    pub fn pick(mut self, pick: Option<MovePick>) -> EngineMoveSource {
+        self.pick = pick;
+
+        self
+    }
+
+    /// set engine thinking in bot state
+    async fn set_engine_thinking(&self, engine_thinking: bool) {
+        let mut state = self.state.lock().await;
+
+        *state = state.clone().set_engine_thinking(engine_thinking);
+    }
+
+    /// name of engine executable in use
+    fn current_engine_name(&self) -> String {
+        match (self.using_backup, &self.backup_engine_name) {
+            (true, Some(backup_engine_name)) => backup_engine_name.to_owned(),
+            _ => self.engine_name.to_owned(),
+        }
+    }
+
+    /// replace crashed engine, by a restarted one while restarts last, then by the backup engine,
+    /// a hung engine process is asked to quit but cannot be killed, as uciengine owns the process
+    fn restart(&mut self, ply: usize) {
+        if let Some(engine) = self.engine.take() {
+            engine.quit();
+        }
+
+        self.ponder = Ponder::new();
+
+        if self.restarts < self.max_restarts {
+            self.restarts += 1;
+
+            self.incidents.push(Incident::new(
+                ply,
+                self.name(),
+                IncidentKind::EngineRestart,
+                format!(
+                    "restarting {} ( restart {} of {} )",
+                    self.current_engine_name(),
+                    self.restarts,
+                    self.max_restarts
+                ),
+            ));
+
+            self.engine = spawn_engine(self.current_engine_name());
+        } else if (!self.using_backup) && self.backup_engine_name.is_some() {
+            self.using_backup = true;
+            self.restarts = 0;
+
+            self.incidents.push(Incident::new(
+                ply,
+                self.name(),
+                IncidentKind::BackupEngine,
+                format!("switching to backup engine {}", self.current_engine_name()),
+            ));
+
+            self.engine = spawn_engine(self.current_engine_name());
+        }
+
+        if self.engine.is_none() && log_enabled!(Level::Info) {
+            info!("no engine available for the rest of the game");
+        }
+    }
+
+    /// stop ponder search if any, an engine that does not stop is restarted,
+    /// as its late bestmove would be taken for the result of the next search
+    async fn stop_ponder(&mut self, ply: usize) {
+        if let Some(engine) = self.engine.clone() {
+            if let Err(err) = self.ponder.stop(&engine).await {
+                self.incidents.push(Incident::new(
+                    ply,
+                    self.name(),
+                    IncidentKind::EngineCrash,
+                    err,
+                ));
+
+                self.restart(ply);
+            }
+        }
+    }
+
+    /// search position of request, Err if the engine crashed
+    async fn search(&mut self, req: &MoveRequest) -> Result<MoveProposal, String> {
+        let engine = match self.engine.clone() {
+            Some(engine) => engine,
+            _ => return Err("engine is not running".to_string()),
+        };
+
+        let mut go_job = GoJob::new()
+            .uci_opt("UCI_Variant", "chess")
+            .pos_startpos()
+            .pos_moves(&req.moves)
+            .tc(req.clock.timecontrol());
+
+        for (key, value) in &self.uci_options {
+            if log_enabled!(Level::Info) {
+                info!("adding uci option {} = {}", key, value);
+            }
+
+            go_job = go_job.uci_opt(key, value);
+        }
+
+        if log_enabled!(Level::Debug) {
+            debug!("mounted go job {:?}", go_job);
+        }
+
+        let start = std::time::Instant::now();
+
+        let timeout = engine_timeout(req);
+
+        let ponder_hit = self.ponder.status(&req.moves) == PonderStatus::Hit;
+
+        if !ponder_hit {
+            self.ponder.stop(&engine).await?;
+        }
+
+        // lines of a multi pv search are only seen as info lines broadcast by the engine,
+        // subscribed to after stopping the ponder search, whose lines are of another position
+        let mut lines_rx = self.pick.map(|_| engine.atx.subscribe());
+
+        let mut lines: Vec<PvLine> = vec![];
+
+        let search = async {
+            match ponder_hit {
+                true => self.ponder.hit(&engine).await,
+                _ => {
+                    if log_enabled!(Level::Info) {
+                        info!("engine start thinking on {:?}", go_job);
+                    }
+
+                    engine.go(go_job).await
+                }
+            }
+        };
+
+        let search = async {
+            match lines_rx.as_mut() {
+                Some(lines_rx) => collect_multipv_lines(search, lines_rx, &mut lines).await,
+                _ => search.await,
+            }
+        };
+
+        // a hung engine is handled like a crashed one
+        let go_result =
+            match tokio::time::timeout(std::time::Duration::from_millis(timeout as u64), search)
+                .await
+            {
+                Ok(go_result) => go_result,
+                _ => return Err(format!("engine did not answer within {} ms", timeout)),
+            };
+
+        let elapsed = start.elapsed().as_millis() as usize;
+
+        if log_enabled!(Level::Debug) {
+            debug!("thinking took {} ms , result {:?}", elapsed, go_result);
+        }
+
+        let clock = req.clock.spend(req.game.bot_white, elapsed);
+
+        if log_enabled!(Level::Info) {
+            info!(
+                "changing bot time from {} to {}",
+                req.bot_time(),
+                clock.time(req.game.bot_white)
+            );
+        }
+
+        let go_result = go_result.map_err(|err| format!("engine did not answer ( {} )", err))?;
+
+        let mut bestmove = match go_result.bestmove {
+            Some(bestmove) => bestmove,
+            _ => return Err("engine answered without bestmove".to_string()),
+        };
+
+        let mut proposal = MoveProposal::new(&bestmove, self.name())
+            .score(go_result.ai.score)
+            .depth(go_result.ai.depth);
+
+        if let Some(pv) = go_result.ai.pv() {
+            proposal = proposal.pv(pv);
+        }
+
+        let mut ponder = go_result.ponder;
+
+        if let Some(pick) = self.pick {
+            let max_depth = lines.iter().map(|line| line.depth).max().unwrap_or(0);
+
+            lines.retain(|line| line.depth + 1 >= max_depth);
+
+            // lines missed by a lagging receiver cannot be picked, without the best line
+            // the best move is played
+            match lines
+                .iter()
+                .find(|line| (line.multipv == 1) && (line.uci == bestmove))
+            {
+                Some(best) => {
+                    // the last info line is the worst line, the best line has the eval of the position
+                    proposal = proposal.score(best.score).depth(best.depth).pv(&best.pv);
+
+                    if let Some(line) = pick.select(&lines, &mut thread_rng()) {
+                        if (line.uci != bestmove) && req.is_legal(&line.uci) {
+                            if log_enabled!(Level::Info) {
+                                info!(
+                                    "picked line {} {} instead of {}",
+                                    line.multipv, line.uci, bestmove
+                                );
+                            }
+
+                            bestmove = line.uci.to_owned();
+                            ponder = line.ponder.to_owned();
+
+                            proposal = MoveProposal::new(&line.uci, self.name())
+                                .score(line.score)
+                                .depth(line.depth)
+                                .pv(&line.pv);
+                        }
+                    }
+                }
+                _ => {
+                    if log_enabled!(Level::Info) {
+                        info!("best line missing, playing best move {}", bestmove);
+                    }
+                }
+            }
+        }
+
+        if log_enabled!(Level::Info) {
+            info!("engine ponder {:?}", ponder);
+        }
+
+        // pondering on an illegal reply would only waste a search
+        if let Some(uci) = ponder {
+            if req.is_legal_reply(&bestmove, &uci) {
+                proposal = proposal.ponder(&uci);
+
+                self.ponder
+                    .start(&engine, &req.moves, &bestmove, uci, clock.timecontrol());
+            }
+        }
+
+        Ok(proposal)
+    }
+}
+
+/// add line of engine info to multi pv lines, replacing the line of its multi pv index
+fn add_multipv_line(lines: &mut Vec<PvLine>, ai: AnalysisInfo) {
+    let pv = match ai.pv() {
+        Some(pv) => pv,
+        _ => return,
+    };
+
+    let uci = match pv.split_whitespace().next() {
+        Some(uci) => uci.to_string(),
+        _ => return,
+    };
+
+    let line = PvLine {
+        multipv: ai.multipv.max(1),
+        uci,
+        score: ai.score,
+        depth: ai.depth,
+        pv,
+        ponder: ai.ponder(),
+    };
+
+    match lines.iter_mut().find(|old| old.multipv == line.multipv) {
+        Some(old) => *old = line,
+        _ => lines.push(line),
+    }
+}
+
+/// result of search, keeping the latest line of each multi pv index broadcast by the engine
+/// while it runs, lines are only missed if the receiver lags more than the channel capacity
+async fn collect_multipv_lines<F, T>(
+    search: F,
+    rx: &mut tokio::sync::broadcast::Receiver<AnalysisInfo>,
+    lines: &mut Vec<PvLine>,
+) -> T
+where
+    F: std::future::Future<Output = T>,
+{
+    use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+
+    tokio::pin!(search);
+
+    let mut open = true;
+
+    loop {
+        tokio::select! {
+            result = &mut search => {
+                // lines are broadcast before the search result is sent
+                loop {
+                    match rx.try_recv() {
+                        Ok(ai) => add_multipv_line(lines, ai),
+                        Err(TryRecvError::Lagged(_)) => continue,
+                        _ => break,
+                    }
+                }
+
+                return result;
+            }
+            ai = rx.recv(), if open => match ai {
+                Ok(ai) => add_multipv_line(lines, ai),
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => open = false,
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl MoveSource for EngineMoveSource {
+    fn name(&self) -> String {
+        "engine".to_string()
+    }
+
+    async fn start_game(&mut self, _game: &GameInfo) {
+        if log_enabled!(Level::Debug) {
+            debug!("created engine for playing game");
+        }
+
+        self.restarts = 0;
+        self.using_backup = false;
+        self.ponder = Ponder::new();
+        self.engine = spawn_engine(&self.engine_name);
+
+        if self.engine.is_none() {
+            self.incidents.push(Incident::new(
+                0,
+                self.name(),
+                IncidentKind::EngineCrash,
+                format!("failed to start {}", self.engine_name),
+            ));
+
+            self.restart(0);
+        }
+    }
+
+    async fn update(&mut self, req: &MoveRequest) {
+        if self.engine.is_some() {
+            let ponder_status = self.ponder.status(&req.moves);
+
+            if log_enabled!(Level::Debug) {
+                debug!("ponder status {:?}", ponder_status);
+            }
+
+            // the engine must not keep searching a position that cannot come up anymore,
+            // or after the game is over
+            if (ponder_status == PonderStatus::Miss)
+                || ((ponder_status != PonderStatus::Idle) && (req.status != "started"))
+            {
+                self.stop_ponder(req.ply()).await;
+            }
+        }
+    }
+
+    async fn get_move(&mut self, req: &MoveRequest) -> Option<MoveProposal> {
+        self.engine.as_ref()?;
+
+        self.set_engine_thinking(true).await;
+
+        let start = std::time::Instant::now();
+
+        let mut result = self.search(req).await;
+
+        while let Err(err) = &result {
+            self.incidents.push(Incident::new(
+                req.ply(),
+                self.name(),
+                IncidentKind::EngineCrash,
+                err,
+            ));
+
+            self.restart(req.ply());
+
+            let elapsed = start.elapsed().as_millis() as usize;
+
+            if self.engine.is_none() || (req.bot_time() < elapsed + ENGINE_RETRY_MIN_TIME) {
+                break;
+            }
+
+            // retry with the time that is left
+            let mut retry_req = req.clone();
+
+            retry_req.clock = req.clock.spend(req.game.bot_white, elapsed);
+
+            if log_enabled!(Level::Info) {
+                info!("retrying search with {} ms left", retry_req.bot_time());
+            }
+
+            result = self.search(&retry_req).await;
+        }
+
+        self.set_engine_thinking(false).await;
+
+        result.ok()
+    }
+
+    async fn move_played(&mut self, req: &MoveRequest, proposal: &MoveProposal) {
+        if proposal.source != self.name() {
+            // a ponder search can only be hit after an engine move
+            self.stop_ponder(req.ply()).await;
+        }
+    }
+
+    fn take_incidents(&mut self) -> Vec<Incident> {
+        std::mem::take(&mut self.incidents)
+    }
+
+    async fn end_game(&mut self) {
+        if let Some(engine) = self.engine.take() {
+            // stop engine before quitting, it is quit anyway if it does not stop
+            let _ = self.ponder.stop(&engine).await;
+
+            // quit engine
+            engine.quit();
+        }
+    }
+}
+
+/// tablebase move source, plays dtz optimal moves from syzygy tables
+pub struct TablebaseMoveSource {
+    /// tablebase
+    pub tablebase: std::sync::Arc<SyzygyTablebase>,
+}
+
+/// implementation of tablebase move source
+impl TablebaseMoveSource {
+    /// create tablebase move source
+    pub fn new(tablebase: std::sync::Arc<SyzygyTablebase>) -> TablebaseMoveSource {
+        TablebaseMoveSource { tablebase }
+    }
+}
+
+#[async_trait]
+impl MoveSource for TablebaseMoveSource {
+    fn name(&self) -> String {
+        "tablebase".to_string()
+    }
+
+    async fn get_move(&mut self, req: &MoveRequest) -> Option<MoveProposal> {
+        if (req.game.variant != "standard") || !self.tablebase.probable(&req.pos) {
+            return None;
+        }
+
+        let tablebase = self.tablebase.clone();
+        let pos = req.pos.clone();
+
+        let m = tokio::task::spawn_blocking(move || tablebase.best_move(&pos))
+            .await
+            .ok()??;
+
+        if log_enabled!(Level::Info) {
+            info!("tablebase move found {} {:?} {:?}", m.uci, m.wdl, m.dtz);
+        }
+
+        Some(MoveProposal::new(&m.uci, self.name()).score(m.score()))
+    }
+}
+
+/// built in search move source, an alpha beta search under the clock without an external engine
+pub struct SearchMoveSource {
+    /// max depth of search
+    pub max_depth: usize,
+}
+
+/// implementation of search move source
+impl SearchMoveSource {
+    /// create search move source
+    pub fn new(max_depth: usize) -> SearchMoveSource {
+        SearchMoveSource { max_depth }
+    }
+}
+
+#[async_trait]
+impl MoveSource for SearchMoveSource {
+    fn name(&self) -> String {
+        "search".to_string()
+    }
+
+    async fn get_move(&mut self, req: &MoveRequest) -> Option<MoveProposal> {
+        if req.game.variant != "standard" {
+            return None;
+        }
+
+        let mut searcher = Searcher::new().max_depth(self.max_depth);
+
+        if req.game.initial_fen == "startpos" {
+            searcher = searcher.history_from_moves(&req.moves);
+        }
+
+        let time = move_time(req.bot_time(), req.clock.inc(req.game.bot_white));
+        let pos = req.pos.clone();
+
+        let result = tokio::task::spawn_blocking(move || searcher.search(&pos, time))
+            .await
+            .ok()??;
+
+        if log_enabled!(Level::Info) {
+            info!(
+                "search move {} score {} depth {} nodes {}",
+                result.uci, result.score, result.depth, result.nodes
+            );
+        }
+
+        Some(
+            MoveProposal::new(&result.uci, self.name())
+                .score(result.uci_score())
+                .depth(result.depth),
+        )
+    }
+}
+
+/// random legal move source
+pub struct RandomMoveSource;
+
+#[async_trait]
+impl MoveSource for RandomMoveSource {
+    fn name(&self) -> String {
+        "random".to_string()
+    }
+
+    async fn get_move(&mut self, req: &MoveRequest) -> Option<MoveProposal> {
+        let legals = req.pos.legals();
+
+        let m = legals.choose(&mut rand::thread_rng())?;
+
+        Some(MoveProposal::new(Uci::from_standard(m), self.name()))
+    }
+}
+
+/// move source backed by a rust function
+pub struct FnMoveSource<F>
+where
+    F: FnMut(&MoveRequest) -> Option<MoveProposal> + Send + Sync,
+{
+    /// name
+    pub name: String,
+    /// function
+    pub f: F,
+}
+
+/// implementation of function move source
+impl<F> FnMoveSource<F>
+where
+    F: FnMut(&MoveRequest) -> Option<MoveProposal> + Send + Sync,
+{
+    /// create function move source
+    pub fn new<T>(name: T, f: F) -> FnMoveSource<F>
+    where
+        T: core::fmt::Display,
+    {
+        FnMoveSource {
+            name: name.to_string(),
+            f,
+        }
+    }
+}
+
+#[async_trait]
+impl<F> MoveSource for FnMoveSource<F>
+where
+    F: FnMut(&MoveRequest) -> Option<MoveProposal> + Send + Sync,
+{
+    fn name(&self) -> String {
+        self.name.to_owned()
+    }
+
+    async fn get_move(&mut self, req: &MoveRequest) -> Option<MoveProposal> {
+        (self.f)(req)
+    }
+}
@@ -0,0 +1,390 @@
+use rand::distributions::WeightedIndex;
+use rand::prelude::*;
+
+use uciengine::analysis::Score;
+
+use crate::book::score_cp;
+use crate::game::*;
+use crate::store::GameStats;
+
+/// max rating difference implied by the score against an opponent
+pub const MAX_HISTORY_DIFFERENCE: i32 = 800;
+
+/// name=value comma separated list to uci options
+fn option_list<T>(list: T) -> Vec<(String, String)>
+where
+    T: core::fmt::Display,
+{
+    list.to_string()
+        .split(',')
+        .filter_map(|item| {
+            let (name, value) = item.split_once('=')?;
+
+            Some((name.trim().to_string(), value.trim().to_string()))
+        })
+        .filter(|(name, _)| !name.is_empty())
+        .collect()
+}
+
+/// uci options used against opponents rated within a range relative to the bot
+#[derive(Debug, Clone, PartialEq)]
+pub struct RatingBand {
+    /// min rating difference of opponent minus bot ( inclusive, optional )
+    pub min: Option<i32>,
+    /// max rating difference of opponent minus bot ( exclusive, optional )
+    pub max: Option<i32>,
+    /// uci options
+    pub options: Vec<(String, String)>,
+}
+
+/// implementation of rating band
+impl RatingBand {
+    /// rating band from {min}..{max}:{name}={value},... , min or max can be left out,
+    /// None if invalid
+    pub fn from_spec<T>(spec: T) -> Option<RatingBand>
+    where
+        T: core::fmt::Display,
+    {
+        let spec = spec.to_string();
+
+        let (range, options) = spec.split_once(':')?;
+
+        let (min, max) = range.trim().split_once("..")?;
+
+        let bound = |bound: &str| match bound.trim() {
+            "" => Some(None),
+            bound => bound.parse().ok().map(Some),
+        };
+
+        Some(RatingBand {
+            min: bound(min)?,
+            max: bound(max)?,
+            options: option_list(options),
+        })
+    }
+
+    /// true if rating difference is in band
+    pub fn contains(&self, difference: i32) -> bool {
+        self.min.map(|min| difference >= min).unwrap_or(true)
+            && self.max.map(|max| difference < max).unwrap_or(true)
+    }
+}
+
+/// rating difference of opponent minus bot implied by the score of the bot against the opponent
+pub fn history_difference(stats: &GameStats) -> Option<i32> {
+    let score = stats.score()?.clamp(0.01, 0.99);
+
+    Some(
+        ((-400.0 * (score / (1.0 - score)).log10()) as i32)
+            .clamp(-MAX_HISTORY_DIFFERENCE, MAX_HISTORY_DIFFERENCE),
+    )
+}
+
+/// uci options of a game by opponent, overriding the uci options of the bot
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpponentOptions {
+    /// rating bands, the first band containing the rating difference is used
+    pub bands: Vec<RatingBand>,
+    /// uci options by lower case opponent name, applied after the band options
+    pub users: std::collections::HashMap<String, Vec<(String, String)>>,
+    /// min number of games against an opponent for using the score instead of ratings
+    pub min_history: usize,
+}
+
+/// implementation of opponent options
+impl OpponentOptions {
+    /// create opponent options without overrides
+    pub fn new() -> OpponentOptions {
+        OpponentOptions {
+            bands: vec![],
+            users: std::collections::HashMap::new(),
+            min_history: 5,
+        }
+    }
+
+    /// set rating bands as semicolon separated list of band specs and return self,
+    /// see RatingBand::from_spec
+    pub fn bands<T>(mut self, bands: T) -> OpponentOptions
+    where
+        T: core::fmt::Display,
+    {
+        self.bands = bands
+            .to_string()
+            .split(';')
+            .filter_map(RatingBand::from_spec)
+            .collect();
+
+        self
+    }
+
+    /// set options of opponents as semicolon separated {user}:{name}={value},... list
+    /// and return self
+    pub fn users<T>(mut self, users: T) -> OpponentOptions
+    where
+        T: core::fmt::Display,
+    {
+        self.users = users
+            .to_string()
+            .split(';')
+            .filter_map(|item| {
+                let (user, options) = item.split_once(':')?;
+
+                Some((user.trim().to_lowercase(), option_list(options)))
+            })
+            .collect();
+
+        self
+    }
+
+    /// set min number of games for using the score against an opponent and return self
+    pub fn min_history(mut self, min_history: usize) -> OpponentOptions {
+        self.min_history = min_history;
+
+        self
+    }
+
+    /// rating difference of opponent minus bot, from the history of games against the opponent
+    /// if there are enough of them, otherwise from ratings, None if unknown
+    pub fn rating_difference(&self, game: &GameInfo, history: Option<&GameStats>) -> Option<i32> {
+        if let Some(history) = history.filter(|history| history.games() >= self.min_history) {
+            return history_difference(history);
+        }
+
+        Some(game.opponent().rating? as i32 - game.bot().rating? as i32)
+    }
+
+    /// uci options of game, band options followed by the options of the opponent
+    pub fn options_for(
+        &self,
+        game: &GameInfo,
+        history: Option<&GameStats>,
+    ) -> Vec<(String, String)> {
+        let mut options: Vec<(String, String)> = self
+            .rating_difference(game, history)
+            .and_then(|difference| self.bands.iter().find(|band| band.contains(difference)))
+            .map(|band| band.options.clone())
+            .unwrap_or_default();
+
+        if let Some(user_options) = self.users.get(&game.opponent().name.to_lowercase()) {
+            options.extend(user_options.iter().cloned());
+        }
+
+        options
+    }
+}
+
+/// default opponent options, without overrides
+impl Default for OpponentOptions {
+    fn default() -> OpponentOptions {
+        OpponentOptions::new()
+    }
+}
+
+/// min UCI_Elo of strength limiting
+pub const HANDICAP_MIN_ELO: i32 = 1320;
+
+/// max UCI_Elo of strength limiting
+pub const HANDICAP_MAX_ELO: i32 = 3190;
+
+/// rating of opponents without rating, for strength limiting
+pub const HANDICAP_DEFAULT_RATING: i32 = 1500;
+
+/// how the bot plays weaker on purpose
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HandicapMode {
+    /// full strength
+    Off,
+    /// UCI_LimitStrength with UCI_Elo matched to the opponent's rating
+    Elo,
+    /// Skill Level matched to the opponent's rating
+    Skill,
+    /// multi pv search, picking among near best moves
+    MultiPv,
+}
+
+/// implementation of handicap mode
+impl HandicapMode {
+    /// mode from name ( off, elo, skill, multipv ), None if unknown
+    pub fn from_name<T>(name: T) -> Option<HandicapMode>
+    where
+        T: core::fmt::Display,
+    {
+        match name.to_string().to_lowercase().as_str() {
+            "off" => Some(HandicapMode::Off),
+            "elo" => Some(HandicapMode::Elo),
+            "skill" => Some(HandicapMode::Skill),
+            "multipv" => Some(HandicapMode::MultiPv),
+            _ => None,
+        }
+    }
+}
+
+/// line of a multi pv search
+#[derive(Debug, Clone)]
+pub struct PvLine {
+    /// multi pv index, 1 for the best line
+    pub multipv: usize,
+    /// first move
+    pub uci: String,
+    /// score from the point of view of the side to move
+    pub score: Score,
+    /// depth
+    pub depth: usize,
+    /// principal variation, space separated uci
+    pub pv: String,
+    /// expected opponent reply, the second move of the principal variation ( optional )
+    pub ponder: Option<String>,
+}
+
+/// picks a move among near best lines of a multi pv search
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MovePick {
+    /// max eval drop below the best line in centipawns
+    pub margin: i32,
+    /// softmax temperature in centipawns, 0 for always picking the best line
+    pub temperature: f64,
+}
+
+/// implementation of move pick
+impl MovePick {
+    /// line picked among lines within the margin, with probability exp(-drop / temperature),
+    /// None without lines
+    pub fn select<'a, R>(&self, lines: &'a [PvLine], rng: &mut R) -> Option<&'a PvLine>
+    where
+        R: Rng + ?Sized,
+    {
+        let best = lines.iter().map(|line| score_cp(&line.score)).max()?;
+
+        let candidates: Vec<&PvLine> = lines
+            .iter()
+            .filter(|line| score_cp(&line.score) >= best - self.margin)
+            .collect();
+
+        if self.temperature <= 0.0 {
+            return candidates
+                .into_iter()
+                .find(|line| score_cp(&line.score) == best);
+        }
+
+        let weights = candidates
+            .iter()
+            .map(|line| ((score_cp(&line.score) - best) as f64 / self.temperature).exp());
+
+        let index = WeightedIndex::new(weights).ok()?.sample(rng);
+
+        Some(candidates[index])
+    }
+}
+
+/// strength limiting in casual games against humans, rated games and games against bots
+/// are played at full strength
+#[derive(Debug, Clone, PartialEq)]
+pub struct Handicap {
+    /// mode
+    pub mode: HandicapMode,
+    /// added to the opponent's rating for UCI_Elo and Skill Level
+    pub elo_offset: i32,
+    /// number of lines of multi pv search
+    pub lines: usize,
+    /// pick of multi pv lines
+    pub pick: MovePick,
+}
+
+/// implementation of handicap
+impl Handicap {
+    /// create handicap playing at full strength
+    pub fn new() -> Handicap {
+        Handicap {
+            mode: HandicapMode::Off,
+            elo_offset: 0,
+            lines: 4,
+            pick: MovePick {
+                margin: 100,
+                temperature: 50.0,
+            },
+        }
+    }
+
+    /// set mode and return self
+    pub fn mode(mut self, mode: HandicapMode) -> Handicap {
+        self.mode = mode;
+
+        self
+    }
+
+    /// set elo offset and return self
+    pub fn elo_offset(mut self, elo_offset: i32) -> Handicap {
+        self.elo_offset = elo_offset;
+
+        self
+    }
+
+    /// set number of lines, margin and temperature of multi pv search and return self
+    pub fn multipv(mut self, lines: usize, margin: i32, temperature: f64) -> Handicap {
+        self.lines = lines;
+        self.pick = MovePick {
+            margin,
+            temperature,
+        };
+
+        self
+    }
+
+    /// true if game is played weaker on purpose
+    pub fn applies(&self, game: &GameInfo) -> bool {
+        (self.mode != HandicapMode::Off) && !game.rated && !game.opponent().is_bot()
+    }
+
+    /// target rating of game, the opponent's rating plus the offset
+    fn target_rating(&self, game: &GameInfo) -> i32 {
+        game.opponent()
+            .rating
+            .map(|rating| rating as i32)
+            .unwrap_or(HANDICAP_DEFAULT_RATING)
+            + self.elo_offset
+    }
+
+    /// uci options limiting the strength in game
+    pub fn uci_options(&self, game: &GameInfo) -> Vec<(String, String)> {
+        if !self.applies(game) {
+            return vec![];
+        }
+
+        let option = |name: &str, value: String| (name.to_string(), value);
+
+        match self.mode {
+            HandicapMode::Elo => vec![
+                option("UCI_LimitStrength", "true".to_string()),
+                option(
+                    "UCI_Elo",
+                    self.target_rating(game)
+                        .clamp(HANDICAP_MIN_ELO, HANDICAP_MAX_ELO)
+                        .to_string(),
+                ),
+            ],
+            HandicapMode::Skill => vec![option(
+                "Skill Level",
+                ((self.target_rating(game) - 1000) / 100)
+                    .clamp(0, 20)
+                    .to_string(),
+            )],
+            HandicapMode::MultiPv => vec![option("MultiPV", self.lines.to_string())],
+            HandicapMode::Off => vec![],
+        }
+    }
+
+    /// pick of multi pv lines in game, None if the best move is played
+    pub fn move_pick(&self, game: &GameInfo) -> Option<MovePick> {
+        match self.mode {
+            HandicapMode::MultiPv if self.applies(game) => Some(self.pick),
+            _ => None,
+        }
+    }
+}
+
+/// default handicap, full strength
+impl Default for Handicap {
+    fn default() -> Handicap {
+        Handicap::new()
+    }
+}
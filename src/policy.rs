@@ -0,0 +1,332 @@
+use uciengine::analysis::Score;
+
+use crate::book::score_cp;
+use crate::game::*;
+
+/// speed:value comma separated list to pairs of lower case speed and value
+fn speed_values<T, V>(list: T) -> std::collections::HashMap<String, V>
+where
+    T: core::fmt::Display,
+    V: std::str::FromStr,
+{
+    list.to_string()
+        .split(',')
+        .filter_map(|item| {
+            let (speed, value) = item.split_once(':')?;
+
+            Some((speed.trim().to_lowercase(), value.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// when the bot resigns
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResignPolicy {
+    /// number of consecutive moves with an eval below the threshold, 0 for never resigning
+    pub moves: usize,
+    /// eval threshold in centipawns from the bot's point of view
+    pub score: i32,
+    /// eval thresholds by speed, overriding score
+    pub speed_scores: std::collections::HashMap<String, i32>,
+    /// resign casual games too
+    pub casual: bool,
+}
+
+/// implementation of resign policy
+impl ResignPolicy {
+    /// create resign policy that never resigns
+    pub fn new() -> ResignPolicy {
+        ResignPolicy {
+            moves: 0,
+            score: -1000,
+            speed_scores: std::collections::HashMap::new(),
+            casual: true,
+        }
+    }
+
+    /// set number of moves and threshold and return self
+    pub fn after(mut self, moves: usize, score: i32) -> ResignPolicy {
+        self.moves = moves;
+        self.score = score;
+
+        self
+    }
+
+    /// set thresholds by speed as comma separated speed:score list and return self
+    pub fn speed_scores<T>(mut self, speed_scores: T) -> ResignPolicy
+    where
+        T: core::fmt::Display,
+    {
+        self.speed_scores = speed_values(speed_scores);
+
+        self
+    }
+
+    /// set resigning casual games and return self
+    pub fn casual(mut self, casual: bool) -> ResignPolicy {
+        self.casual = casual;
+
+        self
+    }
+
+    /// threshold of speed
+    pub fn score_for<T>(&self, speed: T) -> i32
+    where
+        T: core::fmt::Display,
+    {
+        self.speed_scores
+            .get(&speed.to_string().to_lowercase())
+            .cloned()
+            .unwrap_or(self.score)
+    }
+}
+
+/// default resign policy, never resigns
+impl Default for ResignPolicy {
+    fn default() -> ResignPolicy {
+        ResignPolicy::new()
+    }
+}
+
+/// resign decision of a game
+#[derive(Debug, Clone)]
+pub struct GameResign {
+    /// policy
+    pub policy: ResignPolicy,
+    /// number of consecutive moves with an eval below the threshold
+    pub low_moves: usize,
+}
+
+/// implementation of game resign
+impl GameResign {
+    /// create game resign
+    pub fn new(policy: ResignPolicy) -> GameResign {
+        GameResign {
+            policy,
+            low_moves: 0,
+        }
+    }
+
+    /// count eval of a bot move from the bot's point of view, true if the bot should resign,
+    /// moves without eval, like book moves, do not change the count
+    pub fn update(&mut self, game: &GameInfo, score: Option<&Score>) -> bool {
+        if (self.policy.moves == 0) || (!game.rated && !self.policy.casual) {
+            return false;
+        }
+
+        if let Some(score) = score {
+            match score_cp(score) <= self.policy.score_for(&game.speed) {
+                true => self.low_moves += 1,
+                _ => self.low_moves = 0,
+            }
+        }
+
+        self.low_moves >= self.policy.moves
+    }
+}
+
+/// number of plies before the bot offers a draw again
+pub const DRAW_OFFER_INTERVAL: usize = 20;
+
+/// when the bot accepts and offers draws
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrawPolicy {
+    /// number of consecutive moves with an eval near zero, 0 for not drawing by eval
+    pub moves: usize,
+    /// max absolute eval in centipawns counted as near zero
+    pub margin: i32,
+    /// min move number for drawing by eval
+    pub min_move: usize,
+    /// draw when the tablebase says the position is drawn
+    pub tablebase: bool,
+    /// accept draw offers of the opponent
+    pub accept: bool,
+    /// offer draws
+    pub offer: bool,
+}
+
+/// implementation of draw policy
+impl DrawPolicy {
+    /// create draw policy accepting and offering draws only in drawn tablebase positions
+    pub fn new() -> DrawPolicy {
+        DrawPolicy {
+            moves: 0,
+            margin: 20,
+            min_move: 30,
+            tablebase: true,
+            accept: true,
+            offer: true,
+        }
+    }
+
+    /// set eval condition and return self
+    pub fn after(mut self, moves: usize, margin: i32, min_move: usize) -> DrawPolicy {
+        self.moves = moves;
+        self.margin = margin;
+        self.min_move = min_move;
+
+        self
+    }
+
+    /// set drawing by tablebase and return self
+    pub fn tablebase(mut self, tablebase: bool) -> DrawPolicy {
+        self.tablebase = tablebase;
+
+        self
+    }
+
+    /// set accepting and offering draws and return self
+    pub fn accept_offer(mut self, accept: bool, offer: bool) -> DrawPolicy {
+        self.accept = accept;
+        self.offer = offer;
+
+        self
+    }
+}
+
+/// default draw policy, see DrawPolicy::new
+impl Default for DrawPolicy {
+    fn default() -> DrawPolicy {
+        DrawPolicy::new()
+    }
+}
+
+/// draw decision of a game
+#[derive(Debug, Clone)]
+pub struct GameDraw {
+    /// policy
+    pub policy: DrawPolicy,
+    /// number of consecutive moves with an eval near zero
+    pub level_moves: usize,
+    /// ply of the last draw offer
+    pub offered_at: Option<usize>,
+}
+
+/// implementation of game draw
+impl GameDraw {
+    /// create game draw
+    pub fn new(policy: DrawPolicy) -> GameDraw {
+        GameDraw {
+            policy,
+            level_moves: 0,
+            offered_at: None,
+        }
+    }
+
+    /// count eval of a bot move from the bot's point of view at ply, true if a draw is fine,
+    /// tablebase_draw is Some if the tablebase knows the position, moves without eval reset the count
+    pub fn update(
+        &mut self,
+        ply: usize,
+        score: Option<&Score>,
+        tablebase_draw: Option<bool>,
+    ) -> bool {
+        match score {
+            Some(score) if score_cp(score).abs() <= self.policy.margin => self.level_moves += 1,
+            _ => self.level_moves = 0,
+        }
+
+        if let (true, Some(tablebase_draw)) = (self.policy.tablebase, tablebase_draw) {
+            return tablebase_draw;
+        }
+
+        (self.policy.moves > 0)
+            && (ply / 2 + 1 >= self.policy.min_move)
+            && (self.level_moves >= self.policy.moves)
+    }
+
+    /// true if the move at ply should offer or accept a draw, given that a draw is fine
+    /// and whether the opponent offers one
+    pub fn offering_draw(&mut self, ply: usize, draw_fine: bool, opponent_offers: bool) -> bool {
+        if !draw_fine {
+            return false;
+        }
+
+        if opponent_offers {
+            return self.policy.accept;
+        }
+
+        let offer_due = self
+            .offered_at
+            .map(|offered_at| ply >= offered_at + DRAW_OFFER_INTERVAL)
+            .unwrap_or(true);
+
+        if self.policy.offer && offer_due {
+            self.offered_at = Some(ply);
+
+            return true;
+        }
+
+        false
+    }
+}
+
+/// which takeback proposals the bot accepts
+#[derive(Debug, Clone, PartialEq)]
+pub enum TakebackPolicy {
+    /// never accept
+    Decline,
+    /// accept in casual games
+    Casual,
+    /// accept from listed users, lower case
+    Users(Vec<String>),
+}
+
+/// implementation of takeback policy
+impl TakebackPolicy {
+    /// policy from name ( decline, casual or users:{comma separated user names} ), None if unknown
+    pub fn from_name<T>(name: T) -> Option<TakebackPolicy>
+    where
+        T: core::fmt::Display,
+    {
+        let name = name.to_string().to_lowercase();
+
+        match name.as_str() {
+            "decline" => Some(TakebackPolicy::Decline),
+            "casual" => Some(TakebackPolicy::Casual),
+            _ => name.strip_prefix("users:").map(|users| {
+                TakebackPolicy::Users(
+                    users
+                        .split(',')
+                        .map(|user| user.trim().to_string())
+                        .filter(|user| !user.is_empty())
+                        .collect(),
+                )
+            }),
+        }
+    }
+
+    /// true if a takeback proposal of the opponent in game is accepted
+    pub fn accepts(&self, game: &GameInfo) -> bool {
+        match self {
+            TakebackPolicy::Decline => false,
+            TakebackPolicy::Casual => !game.rated,
+            TakebackPolicy::Users(users) => users.contains(&game.opponent().name.to_lowercase()),
+        }
+    }
+}
+
+/// default takeback policy, never accept
+impl Default for TakebackPolicy {
+    fn default() -> TakebackPolicy {
+        TakebackPolicy::Decline
+    }
+}
+
+/// number of moves taken back, if the new game moves are the old ones with moves removed
+pub fn taken_back<O, N>(old_moves: O, new_moves: N) -> Option<usize>
+where
+    O: core::fmt::Display,
+    N: core::fmt::Display,
+{
+    let old_moves = old_moves.to_string();
+    let new_moves = new_moves.to_string();
+
+    let old: Vec<&str> = old_moves.split_whitespace().collect();
+    let new: Vec<&str> = new_moves.split_whitespace().collect();
+
+    match (new.len() < old.len()) && old.starts_with(&new) {
+        true => Some(old.len() - new.len()),
+        _ => None,
+    }
+}
@@ -0,0 +1,200 @@
+use log::{info, log_enabled, Level};
+
+use uciengine::uciengine::*;
+
+/// time allowed for the engine to answer a stop with a bestmove, in milliseconds
+pub const PONDER_STOP_TIMEOUT: u64 = 5000;
+
+/// append a uci move to a space separated move list
+pub fn append_move<M, U>(moves: M, uci: U) -> String
+where
+    M: core::fmt::Display,
+    U: core::fmt::Display,
+{
+    let moves = moves.to_string();
+
+    match moves.as_str() {
+        "" => uci.to_string(),
+        _ => format!("{} {}", moves, uci),
+    }
+}
+
+/// ponder search started after a bot move
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingPonder {
+    /// game moves before the bot move
+    pub moves: String,
+    /// bot move
+    pub bestmove: String,
+    /// expected opponent reply, the engine is searching the position after it
+    pub ponder: String,
+}
+
+/// implementation of pending ponder
+impl PendingPonder {
+    /// moves of the position waiting for the opponent reply
+    pub fn waiting_moves(&self) -> String {
+        append_move(&self.moves, &self.bestmove)
+    }
+
+    /// moves of the position the engine is pondering on
+    pub fn ponder_moves(&self) -> String {
+        append_move(self.waiting_moves(), &self.ponder)
+    }
+}
+
+/// status of pondering with respect to the current game moves
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PonderStatus {
+    /// no ponder search is running
+    Idle,
+    /// ponder search is running, opponent has not moved yet
+    Waiting,
+    /// opponent played the expected move
+    Hit,
+    /// game moves no longer match the ponder search ( wrong reply or takeback )
+    Miss,
+}
+
+/// ponder state of a game
+#[derive(Debug, Clone)]
+pub struct Ponder {
+    /// pending ponder search
+    pub pending: Option<PendingPonder>,
+    /// time allowed for the engine to answer a stop with a bestmove, in milliseconds
+    pub stop_timeout: u64,
+}
+
+/// ponder implementation
+impl Ponder {
+    /// create new idle ponder state
+    pub fn new() -> Ponder {
+        Ponder {
+            pending: None,
+            stop_timeout: PONDER_STOP_TIMEOUT,
+        }
+    }
+
+    /// set stop timeout and return self
+    pub fn stop_timeout(mut self, stop_timeout: u64) -> Ponder {
+        self.stop_timeout = stop_timeout;
+
+        self
+    }
+
+    /// true if a ponder search is running
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// status of pondering for game moves
+    pub fn status<T>(&self, moves: T) -> PonderStatus
+    where
+        T: core::fmt::Display,
+    {
+        let moves = moves.to_string();
+
+        match &self.pending {
+            Some(pending) => {
+                if moves == pending.waiting_moves() {
+                    PonderStatus::Waiting
+                } else if moves == pending.ponder_moves() {
+                    PonderStatus::Hit
+                } else {
+                    PonderStatus::Miss
+                }
+            }
+            _ => PonderStatus::Idle,
+        }
+    }
+
+    /// start pondering on the expected opponent reply,
+    /// any ponder search still running is expected to have been resolved by the caller
+    pub fn start<M, B, P>(
+        &mut self,
+        engine: &UciEngine,
+        moves: M,
+        bestmove: B,
+        ponder: P,
+        tc: Timecontrol,
+    ) where
+        M: core::fmt::Display,
+        B: core::fmt::Display,
+        P: core::fmt::Display,
+    {
+        let pending = PendingPonder {
+            moves: moves.to_string(),
+            bestmove: bestmove.to_string(),
+            ponder: ponder.to_string(),
+        };
+
+        if log_enabled!(Level::Info) {
+            info!("start pondering on {}", pending.ponder_moves());
+        }
+
+        let go_job = GoJob::new()
+            .uci_opt("UCI_Variant", "chess")
+            .pos_startpos()
+            .pos_moves(pending.ponder_moves())
+            .ponder()
+            .tc(tc);
+
+        // a ponder job has no result, the search is resolved by ponderhit or stop
+        drop(engine.go(go_job));
+
+        self.pending = Some(pending);
+    }
+
+    /// opponent played the expected move, turn ponder search into a normal search
+    /// and wait for its result
+    pub async fn hit(
+        &mut self,
+        engine: &UciEngine,
+    ) -> Result<GoResult, tokio::sync::oneshot::error::RecvError> {
+        if log_enabled!(Level::Info) {
+            info!("ponderhit, waiting for result");
+        }
+
+        self.pending = None;
+
+        engine.go(GoJob::new().ponderhit()).await
+    }
+
+    /// stop ponder search if any and wait for its bestmove, which is discarded,
+    /// Err if the engine does not answer, its stop job is then still queued and would
+    /// take the bestmove of the next search
+    pub async fn stop(&mut self, engine: &UciEngine) -> Result<(), String> {
+        if let Some(pending) = self.pending.take() {
+            if log_enabled!(Level::Info) {
+                info!("stopping ponder search on {}", pending.ponder_moves());
+            }
+
+            let result = tokio::time::timeout(
+                tokio::time::Duration::from_millis(self.stop_timeout),
+                engine.go(GoJob::new().pondermiss()),
+            )
+            .await;
+
+            if log_enabled!(Level::Info) {
+                info!("ponder search stopped, result {:?}", result);
+            }
+
+            return match result {
+                Ok(Ok(_)) => Ok(()),
+                Ok(Err(err)) => Err(format!("engine did not stop pondering ( {} )", err)),
+                _ => Err(format!(
+                    "engine did not stop pondering within {} ms",
+                    self.stop_timeout
+                )),
+            };
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Ponder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,158 @@
+use log::{info, log_enabled, Level};
+
+use pgnparse::parser::*;
+
+use shakmaty::san::San;
+use shakmaty::uci::Uci;
+use shakmaty::{Chess, Position};
+
+/// opening repertoire of the bot, lines of uci moves from the starting position by bot color,
+/// lines include the moves of both sides
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Repertoire {
+    /// lines played as white, in order of priority
+    pub white: Vec<Vec<String>>,
+    /// lines played as black, in order of priority
+    pub black: Vec<Vec<String>>,
+}
+
+/// implementation of repertoire
+impl Repertoire {
+    /// create empty repertoire
+    pub fn new() -> Repertoire {
+        Repertoire::default()
+    }
+
+    /// line from space separated san or uci moves, move numbers and results are skipped
+    pub fn parse_line<T>(line: T) -> Result<Vec<String>, String>
+    where
+        T: core::fmt::Display,
+    {
+        let mut pos = Chess::default();
+
+        let mut ucis = vec![];
+
+        for token in line.to_string().split_whitespace() {
+            let token = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+
+            if token.is_empty() || ["1-0", "0-1", "1/2-1/2", "*"].contains(&token) {
+                continue;
+            }
+
+            let m = match token
+                .parse::<Uci>()
+                .ok()
+                .and_then(|uci| uci.to_move(&pos).ok())
+            {
+                Some(m) => m,
+                _ => token
+                    .parse::<San>()
+                    .ok()
+                    .and_then(|san| san.to_move(&pos).ok())
+                    .ok_or(format!("illegal move {} in {}", token, line))?,
+            };
+
+            ucis.push(Uci::from_standard(&m).to_string());
+
+            pos.play_unchecked(&m);
+        }
+
+        Ok(ucis)
+    }
+
+    /// lines of pgn file, every game is a line
+    pub fn load_pgn<T>(path: T) -> Result<Vec<Vec<String>>, String>
+    where
+        T: core::fmt::Display,
+    {
+        let path = path.to_string();
+
+        let iter = PgnIterator::new(&path).ok_or(format!("could not open {}", path))?;
+
+        Ok(iter
+            .map(parse_pgn_to_rust_struct)
+            .map(|game| game.moves.iter().map(|m| m.uci.to_owned()).collect())
+            .filter(|line: &Vec<String>| !line.is_empty())
+            .collect())
+    }
+
+    /// add line for color and return self, a line that cannot be parsed is logged and skipped
+    pub fn line<T>(mut self, bot_white: bool, line: T) -> Repertoire
+    where
+        T: core::fmt::Display,
+    {
+        match Repertoire::parse_line(line) {
+            Ok(line) if !line.is_empty() => match bot_white {
+                true => self.white.push(line),
+                _ => self.black.push(line),
+            },
+            Ok(_) => {}
+            Err(err) => {
+                if log_enabled!(Level::Info) {
+                    info!("repertoire line skipped : {}", err);
+                }
+            }
+        }
+
+        self
+    }
+
+    /// add lines for color and return self, lines are a pgn file or separated by ;
+    pub fn lines<T>(mut self, bot_white: bool, lines: T) -> Repertoire
+    where
+        T: core::fmt::Display,
+    {
+        let lines = lines.to_string();
+
+        if lines.trim().to_lowercase().ends_with(".pgn") {
+            match Repertoire::load_pgn(lines.trim()) {
+                Ok(lines) => match bot_white {
+                    true => self.white.extend(lines),
+                    _ => self.black.extend(lines),
+                },
+                Err(err) => {
+                    if log_enabled!(Level::Info) {
+                        info!("repertoire not loaded : {}", err);
+                    }
+                }
+            }
+
+            return self;
+        }
+
+        for line in lines.split(';') {
+            self = self.line(bot_white, line);
+        }
+
+        self
+    }
+
+    /// true if there are no lines
+    pub fn is_empty(&self) -> bool {
+        self.white.is_empty() && self.black.is_empty()
+    }
+
+    /// repertoire move after space separated uci moves from the starting position,
+    /// the next move of the first line the game follows, None if the game left the repertoire
+    pub fn get_move<T>(&self, bot_white: bool, moves: T) -> Option<String>
+    where
+        T: core::fmt::Display,
+    {
+        let moves = moves.to_string();
+
+        let moves: Vec<&str> = moves.split_whitespace().collect();
+
+        let lines = match bot_white {
+            true => &self.white,
+            _ => &self.black,
+        };
+
+        lines
+            .iter()
+            .filter(|line| {
+                line.len() > moves.len() && line.iter().zip(moves.iter()).all(|(a, b)| a == b)
+            })
+            .map(|line| line[moves.len()].to_owned())
+            .next()
+    }
+}
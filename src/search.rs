@@ -0,0 +1,471 @@
+use log::{debug, log_enabled, Level};
+
+use shakmaty::uci::Uci;
+use shakmaty::{Chess, Color, Move, MoveList, Position, Role, Setup};
+
+use uciengine::analysis::Score;
+
+use crate::polyglot::polyglot_key;
+
+/// score of being mated at the root, mate in n plies scores MATE_SCORE - n
+pub const MATE_SCORE: i32 = 100000;
+
+/// scores above this are mates
+const MATE_BOUND: i32 = MATE_SCORE - 1000;
+
+/// nodes between checks of the deadline
+const TIME_CHECK_NODES: usize = 1024;
+
+/// piece values in centipawns, indexed by role
+const PIECE_VALUES: [i32; 6] = [100, 320, 330, 500, 900, 0];
+
+/// piece square tables from white's point of view, indexed by role,
+/// rows from the 8th rank down to the 1st
+#[rustfmt::skip]
+const PIECE_SQUARE_TABLES: [[i32; 64]; 6] = [
+    // pawn
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+         50,  50,  50,  50,  50,  50,  50,  50,
+         10,  10,  20,  30,  30,  20,  10,  10,
+          5,   5,  10,  25,  25,  10,   5,   5,
+          0,   0,   0,  20,  20,   0,   0,   0,
+          5,  -5, -10,   0,   0, -10,  -5,   5,
+          5,  10,  10, -20, -20,  10,  10,   5,
+          0,   0,   0,   0,   0,   0,   0,   0,
+    ],
+    // knight
+    [
+        -50, -40, -30, -30, -30, -30, -40, -50,
+        -40, -20,   0,   0,   0,   0, -20, -40,
+        -30,   0,  10,  15,  15,  10,   0, -30,
+        -30,   5,  15,  20,  20,  15,   5, -30,
+        -30,   0,  15,  20,  20,  15,   0, -30,
+        -30,   5,  10,  15,  15,  10,   5, -30,
+        -40, -20,   0,   5,   5,   0, -20, -40,
+        -50, -40, -30, -30, -30, -30, -40, -50,
+    ],
+    // bishop
+    [
+        -20, -10, -10, -10, -10, -10, -10, -20,
+        -10,   0,   0,   0,   0,   0,   0, -10,
+        -10,   0,   5,  10,  10,   5,   0, -10,
+        -10,   5,   5,  10,  10,   5,   5, -10,
+        -10,   0,  10,  10,  10,  10,   0, -10,
+        -10,  10,  10,  10,  10,  10,  10, -10,
+        -10,   5,   0,   0,   0,   0,   5, -10,
+        -20, -10, -10, -10, -10, -10, -10, -20,
+    ],
+    // rook
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+          5,  10,  10,  10,  10,  10,  10,   5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+         -5,   0,   0,   0,   0,   0,   0,  -5,
+          0,   0,   0,   5,   5,   0,   0,   0,
+    ],
+    // queen
+    [
+        -20, -10, -10,  -5,  -5, -10, -10, -20,
+        -10,   0,   0,   0,   0,   0,   0, -10,
+        -10,   0,   5,   5,   5,   5,   0, -10,
+         -5,   0,   5,   5,   5,   5,   0,  -5,
+          0,   0,   5,   5,   5,   5,   0,  -5,
+        -10,   5,   5,   5,   5,   5,   0, -10,
+        -10,   0,   5,   0,   0,   0,   0, -10,
+        -20, -10, -10,  -5,  -5, -10, -10, -20,
+    ],
+    // king, middlegame
+    [
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -30, -40, -40, -50, -50, -40, -40, -30,
+        -20, -30, -30, -40, -40, -30, -30, -20,
+        -10, -20, -20, -20, -20, -20, -20, -10,
+         20,  20,   0,   0,   0,   0,  20,  20,
+         20,  30,  10,   0,   0,  10,  30,  20,
+    ],
+];
+
+/// king piece square table of the endgame, from white's point of view
+#[rustfmt::skip]
+const KING_ENDGAME_TABLE: [i32; 64] = [
+    -50, -40, -30, -20, -20, -30, -40, -50,
+    -30, -20, -10,   0,   0, -10, -20, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -30,   0,   0,   0,   0, -30, -30,
+    -50, -30, -30, -30, -30, -30, -30, -50,
+];
+
+/// index of role in tables
+fn role_index(role: Role) -> usize {
+    role as usize - 1
+}
+
+/// static evaluation of position in centipawns for the side to move
+pub fn evaluate(pos: &Chess) -> i32 {
+    let board = pos.board();
+
+    let queens = board.by_role(Role::Queen).count();
+    let minors_and_rooks =
+        (board.by_role(Role::Knight) | board.by_role(Role::Bishop) | board.by_role(Role::Rook))
+            .count();
+
+    let endgame = (queens == 0) || (queens <= 2 && minors_and_rooks <= 2);
+
+    let mut score = 0;
+
+    for (square, piece) in board.pieces() {
+        let index = match piece.color {
+            Color::White => usize::from(square) ^ 56,
+            Color::Black => usize::from(square),
+        };
+
+        let table_score = match (piece.role, endgame) {
+            (Role::King, true) => KING_ENDGAME_TABLE[index],
+            (role, _) => PIECE_SQUARE_TABLES[role_index(role)][index],
+        };
+
+        let value = PIECE_VALUES[role_index(piece.role)] + table_score;
+
+        match piece.color == pos.turn() {
+            true => score += value,
+            _ => score -= value,
+        }
+    }
+
+    score
+}
+
+/// move ordering score, promotions and captures of valuable pieces by cheap pieces first
+fn move_order(m: &Move) -> i32 {
+    let promotion = m
+        .promotion()
+        .map(|role| PIECE_VALUES[role_index(role)])
+        .unwrap_or(0);
+
+    let capture = m
+        .capture()
+        .map(|role| 10 * PIECE_VALUES[role_index(role)] - PIECE_VALUES[role_index(m.role())])
+        .unwrap_or(0);
+
+    promotion + capture
+}
+
+/// moves sorted for search, first move first if given
+fn ordered(mut moves: MoveList, first: Option<&Move>) -> MoveList {
+    moves.sort_by_key(|m| match Some(m) == first {
+        true => i32::MIN,
+        _ => -move_order(m),
+    });
+
+    moves
+}
+
+/// result of a search
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    /// best move in uci notation
+    pub uci: String,
+    /// score in centipawns for the side to move, mates are scored from MATE_SCORE
+    pub score: i32,
+    /// depth of last completed iteration
+    pub depth: usize,
+    /// number of searched nodes
+    pub nodes: usize,
+}
+
+/// implementation of search result
+impl SearchResult {
+    /// score as uci score
+    pub fn uci_score(&self) -> Score {
+        match self.score {
+            score if score > MATE_BOUND => Score::Mate((MATE_SCORE - score + 1) / 2),
+            score if score < -MATE_BOUND => Score::Mate(-(MATE_SCORE + score + 1) / 2),
+            score => Score::Cp(score),
+        }
+    }
+}
+
+/// alpha beta searcher with quiescence and iterative deepening
+#[derive(Debug, Clone)]
+pub struct Searcher {
+    /// max depth of iterative deepening
+    pub max_depth: usize,
+    /// keys of positions played before the root, for detecting repetitions
+    pub history: Vec<u64>,
+    /// time the search has to stop at
+    deadline: Option<std::time::Instant>,
+    /// keys of positions from the root to the current node
+    path: Vec<u64>,
+    /// nodes searched
+    nodes: usize,
+    /// node count at which the deadline is looked at next
+    next_time_check: usize,
+    /// true if the search ran out of time
+    stopped: bool,
+}
+
+/// implementation of searcher
+impl Searcher {
+    /// create searcher
+    pub fn new() -> Searcher {
+        Searcher {
+            max_depth: 64,
+            history: vec![],
+            deadline: None,
+            path: vec![],
+            nodes: 0,
+            next_time_check: 0,
+            stopped: false,
+        }
+    }
+
+    /// set max depth and return self
+    pub fn max_depth(mut self, max_depth: usize) -> Searcher {
+        self.max_depth = max_depth.max(1);
+
+        self
+    }
+
+    /// set history to the positions before space separated uci moves from the starting position,
+    /// and return self
+    pub fn history_from_moves<T>(mut self, moves: T) -> Searcher
+    where
+        T: core::fmt::Display,
+    {
+        let mut pos = Chess::default();
+
+        self.history = vec![];
+
+        for uci in moves.to_string().split_whitespace() {
+            match uci
+                .parse::<Uci>()
+                .ok()
+                .and_then(|uci| uci.to_move(&pos).ok())
+            {
+                Some(m) => {
+                    self.history.push(polyglot_key(&pos));
+
+                    pos.play_unchecked(&m);
+                }
+                _ => break,
+            }
+        }
+
+        self
+    }
+
+    /// true if the search has to stop, the clock is only looked at every few nodes
+    fn out_of_time(&mut self) -> bool {
+        if !self.stopped && self.nodes >= self.next_time_check {
+            self.next_time_check = self.nodes + TIME_CHECK_NODES;
+
+            if let Some(deadline) = self.deadline {
+                self.stopped = std::time::Instant::now() >= deadline;
+            }
+        }
+
+        self.stopped
+    }
+
+    /// true if position with key repeats a position of the search path or the game
+    /// since the last capture or pawn move
+    fn is_repetition(&self, key: u64, halfmoves: u32) -> bool {
+        self.path
+            .iter()
+            .rev()
+            .chain(self.history.iter().rev())
+            .take(halfmoves as usize)
+            .skip(1)
+            .any(|previous| *previous == key)
+    }
+
+    /// quiescence search of captures
+    fn quiesce(&mut self, pos: &Chess, mut alpha: i32, beta: i32) -> i32 {
+        self.nodes += 1;
+
+        if self.out_of_time() {
+            return 0;
+        }
+
+        let stand_pat = evaluate(pos);
+
+        if stand_pat >= beta {
+            return beta;
+        }
+
+        alpha = alpha.max(stand_pat);
+
+        let mut captures = MoveList::new();
+
+        pos.capture_moves(&mut captures);
+
+        for m in ordered(captures, None).iter() {
+            let mut child = pos.clone();
+
+            child.play_unchecked(m);
+
+            let score = -self.quiesce(&child, -beta, -alpha);
+
+            if self.stopped {
+                return 0;
+            }
+
+            if score >= beta {
+                return beta;
+            }
+
+            alpha = alpha.max(score);
+        }
+
+        alpha
+    }
+
+    /// alpha beta search of position, returns score and best move
+    fn alpha_beta(
+        &mut self,
+        pos: &Chess,
+        depth: usize,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+        first: Option<&Move>,
+    ) -> (i32, Option<Move>) {
+        self.nodes += 1;
+
+        if self.out_of_time() {
+            return (0, None);
+        }
+
+        let key = polyglot_key(pos);
+
+        if (ply > 0)
+            && (pos.is_insufficient_material()
+                || (pos.halfmoves() >= 100)
+                || self.is_repetition(key, pos.halfmoves()))
+        {
+            return (0, None);
+        }
+
+        let legals = pos.legals();
+
+        if legals.is_empty() {
+            return match pos.is_check() {
+                true => (-MATE_SCORE + ply as i32, None),
+                _ => (0, None),
+            };
+        }
+
+        if depth == 0 {
+            return (self.quiesce(pos, alpha, beta), None);
+        }
+
+        self.path.push(key);
+
+        let mut best = None;
+
+        for m in ordered(legals, first).iter() {
+            let mut child = pos.clone();
+
+            child.play_unchecked(m);
+
+            let (score, _) = self.alpha_beta(&child, depth - 1, ply + 1, -beta, -alpha, None);
+            let score = -score;
+
+            if self.stopped {
+                break;
+            }
+
+            if score > alpha || best.is_none() {
+                alpha = alpha.max(score);
+                best = Some(m.clone());
+            }
+
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        self.path.pop();
+
+        (alpha, best)
+    }
+
+    /// search position until max depth, a mate is found or the time in milliseconds is up,
+    /// the first iteration is always completed, None if there are no legal moves
+    pub fn search(&mut self, pos: &Chess, time: usize) -> Option<SearchResult> {
+        let start = std::time::Instant::now();
+
+        self.nodes = 0;
+        self.next_time_check = 0;
+        self.stopped = false;
+        self.path = vec![];
+        self.deadline = None;
+
+        let mut result: Option<(Move, i32, usize)> = None;
+
+        for depth in 1..=self.max_depth {
+            let first = result.as_ref().map(|(m, _, _)| m.clone());
+
+            let (score, best) =
+                self.alpha_beta(pos, depth, 0, -MATE_SCORE, MATE_SCORE, first.as_ref());
+
+            if self.stopped {
+                break;
+            }
+
+            let m = best?;
+
+            if log_enabled!(Level::Debug) {
+                debug!(
+                    "search depth {} score {} move {} nodes {}",
+                    depth,
+                    score,
+                    Uci::from_standard(&m),
+                    self.nodes
+                );
+            }
+
+            result = Some((m, score, depth));
+
+            if score.abs() > MATE_BOUND {
+                break;
+            }
+
+            self.deadline = Some(start + std::time::Duration::from_millis(time as u64));
+
+            // the next iteration takes several times as long, do not start it if it cannot finish
+            if start.elapsed().as_millis() as usize * 4 > time {
+                break;
+            }
+        }
+
+        result.map(|(m, score, depth)| SearchResult {
+            uci: Uci::from_standard(&m).to_string(),
+            score,
+            depth,
+            nodes: self.nodes,
+        })
+    }
+}
+
+/// default searcher
+impl Default for Searcher {
+    fn default() -> Searcher {
+        Searcher::new()
+    }
+}
+
+/// time for a move in milliseconds, a fortieth of the remaining time plus most of the increment,
+/// never more than a quarter of the remaining time
+pub fn move_time(time: usize, inc: usize) -> usize {
+    (time / 40 + inc * 3 / 4).min(time / 4)
+}
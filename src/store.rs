@@ -0,0 +1,332 @@
+use log::{info, log_enabled, Level};
+
+use rusqlite::{params, Connection};
+
+use crate::archive::GameOpening;
+use crate::book::score_cp;
+use crate::game::*;
+
+/// tables of the store
+const SCHEMA: &str = "
+create table if not exists games (
+    id text primary key,
+    opponent text not null,
+    opponent_rating integer,
+    opponent_title text,
+    speed text not null,
+    variant text not null,
+    rated integer not null,
+    bot_white integer not null,
+    termination text not null,
+    result text,
+    eco text,
+    opening text,
+    moves text not null,
+    created_at text not null,
+    duration integer not null
+);
+create table if not exists challenges (
+    id text primary key,
+    challenger text,
+    challenger_rating integer,
+    speed text not null,
+    variant text not null,
+    rated integer not null,
+    accepted integer not null,
+    reasons text not null,
+    received_at text not null
+);
+create table if not exists moves (
+    game_id text not null,
+    ply integer not null,
+    uci text not null,
+    source text not null,
+    score integer,
+    depth integer,
+    time integer not null,
+    primary key (game_id, ply)
+);
+";
+
+/// won, drawn and lost games
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct GameStats {
+    /// number of games won
+    pub wins: usize,
+    /// number of games drawn
+    pub draws: usize,
+    /// number of games lost
+    pub losses: usize,
+}
+
+/// implementation of game stats
+impl GameStats {
+    /// number of games
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    /// score of the bot between 0 and 1, None without games
+    pub fn score(&self) -> Option<f64> {
+        match self.games() {
+            0 => None,
+            games => Some((self.wins as f64 + self.draws as f64 / 2.0) / games as f64),
+        }
+    }
+}
+
+/// challenge and the decision about it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChallengeDecision {
+    /// challenge id
+    pub id: String,
+    /// challenger name ( optional )
+    pub challenger: Option<String>,
+    /// challenger rating ( optional )
+    pub challenger_rating: Option<u16>,
+    /// speed
+    pub speed: String,
+    /// variant key
+    pub variant: String,
+    /// rated
+    pub rated: bool,
+    /// accepted
+    pub accepted: bool,
+    /// reasons of declining
+    pub reasons: Vec<String>,
+}
+
+/// result of outcome as stored
+fn result_name(outcome: &GameOutcome) -> Option<&'static str> {
+    outcome.result.map(|result| match result {
+        BotResult::Win => "win",
+        BotResult::Draw => "draw",
+        BotResult::Loss => "loss",
+    })
+}
+
+/// sqlite store of games, challenges and move decisions
+pub struct GameStore {
+    /// connection, locked as it cannot be shared between threads
+    conn: std::sync::Mutex<Connection>,
+}
+
+/// implementation of game store
+impl GameStore {
+    /// store of connection, tables are created if missing
+    fn from_connection(conn: Connection) -> Result<GameStore, String> {
+        conn.execute_batch(SCHEMA)
+            .map_err(|err| format!("{:?}", err))?;
+
+        Ok(GameStore {
+            conn: std::sync::Mutex::new(conn),
+        })
+    }
+
+    /// open store in database file, the file is created if missing
+    pub fn open<T>(path: T) -> Result<GameStore, String>
+    where
+        T: core::fmt::Display,
+    {
+        let conn = Connection::open(path.to_string()).map_err(|err| format!("{:?}", err))?;
+
+        GameStore::from_connection(conn)
+    }
+
+    /// open store in memory
+    pub fn open_in_memory() -> Result<GameStore, String> {
+        let conn = Connection::open_in_memory().map_err(|err| format!("{:?}", err))?;
+
+        GameStore::from_connection(conn)
+    }
+
+    /// run function with the connection
+    fn with_conn<F, R>(&self, f: F) -> Result<R, String>
+    where
+        F: FnOnce(&mut Connection) -> rusqlite::Result<R>,
+    {
+        let mut conn = self.conn.lock().map_err(|err| format!("{:?}", err))?;
+
+        f(&mut conn).map_err(|err| format!("{:?}", err))
+    }
+
+    /// add finished game with its move decisions, a game added before is replaced
+    pub fn add_game(
+        &self,
+        record: &GameRecord,
+        outcome: &GameOutcome,
+        opening: Option<&GameOpening>,
+    ) -> Result<(), String> {
+        let info = &record.info;
+        let opponent = info.opponent();
+
+        let duration = (chrono::Utc::now() - info.created_at).num_milliseconds();
+
+        self.with_conn(|conn| {
+            let tx = conn.transaction()?;
+
+            tx.execute(
+                "insert or replace into games values
+                (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+                params![
+                    info.id,
+                    opponent.name,
+                    opponent.rating,
+                    opponent.title,
+                    info.speed,
+                    info.variant,
+                    info.rated,
+                    info.bot_white,
+                    format!("{:?}", outcome.termination),
+                    result_name(outcome),
+                    opening.map(|opening| opening.eco.to_owned()),
+                    opening.map(|opening| opening.name.to_owned()),
+                    record.moves,
+                    info.created_at.to_rfc3339(),
+                    duration,
+                ],
+            )?;
+
+            tx.execute("delete from moves where game_id = ?1", params![info.id])?;
+
+            for decision in record.decisions.iter() {
+                tx.execute(
+                    "insert into moves values (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        info.id,
+                        decision.ply as i64,
+                        decision.uci,
+                        decision.source,
+                        decision.score.as_ref().map(score_cp),
+                        decision.depth.map(|depth| depth as i64),
+                        decision.time as i64,
+                    ],
+                )?;
+            }
+
+            tx.commit()
+        })?;
+
+        if log_enabled!(Level::Info) {
+            info!("game {} stored", info.id);
+        }
+
+        Ok(())
+    }
+
+    /// add challenge decision, a challenge added before is replaced
+    pub fn add_challenge(&self, challenge: &ChallengeDecision) -> Result<(), String> {
+        self.with_conn(|conn| {
+            conn.execute(
+                "insert or replace into challenges values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    challenge.id,
+                    challenge.challenger,
+                    challenge.challenger_rating,
+                    challenge.speed,
+                    challenge.variant,
+                    challenge.rated,
+                    challenge.accepted,
+                    challenge.reasons.join("; "),
+                    chrono::Utc::now().to_rfc3339(),
+                ],
+            )
+        })
+        .map(|_| ())
+    }
+
+    /// stats of games matching the where clause, grouped by column, or all together
+    fn stats(
+        &self,
+        group: Option<&str>,
+        filter: &str,
+        param: Option<&str>,
+    ) -> Result<Vec<(String, GameStats)>, String> {
+        let key = group.unwrap_or("''");
+
+        let sql = format!(
+            "select {key}, result, count(*) from games where result is not null {filter}
+            group by {key}, result",
+            key = key,
+            filter = filter
+        );
+
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(&sql)?;
+
+            let mut rows = match param {
+                Some(param) => stmt.query(params![param])?,
+                _ => stmt.query([])?,
+            };
+
+            let mut stats: Vec<(String, GameStats)> = vec![];
+
+            while let Some(row) = rows.next()? {
+                let key: Option<String> = row.get(0)?;
+                let result: String = row.get(1)?;
+                let count: i64 = row.get(2)?;
+
+                let key = key.unwrap_or_else(|| "?".to_string());
+
+                let index = match stats.iter().position(|(k, _)| *k == key) {
+                    Some(index) => index,
+                    _ => {
+                        stats.push((key, GameStats::default()));
+
+                        stats.len() - 1
+                    }
+                };
+
+                let entry = &mut stats[index].1;
+
+                match result.as_str() {
+                    "win" => entry.wins += count as usize,
+                    "draw" => entry.draws += count as usize,
+                    _ => entry.losses += count as usize,
+                }
+            }
+
+            Ok(stats)
+        })
+    }
+
+    /// stats of all games
+    pub fn totals(&self) -> Result<GameStats, String> {
+        Ok(self
+            .stats(None, "", None)?
+            .pop()
+            .map(|(_, stats)| stats)
+            .unwrap_or_default())
+    }
+
+    /// stats of games against opponent
+    pub fn opponent<T>(&self, name: T) -> Result<GameStats, String>
+    where
+        T: core::fmt::Display,
+    {
+        Ok(self
+            .stats(
+                None,
+                "and lower(opponent) = lower(?1)",
+                Some(&name.to_string()),
+            )?
+            .pop()
+            .map(|(_, stats)| stats)
+            .unwrap_or_default())
+    }
+
+    /// stats by lower case opponent name
+    pub fn by_opponent(&self) -> Result<Vec<(String, GameStats)>, String> {
+        self.stats(Some("lower(opponent)"), "", None)
+    }
+
+    /// stats by opening eco code, ? for games with unknown opening
+    pub fn by_opening(&self) -> Result<Vec<(String, GameStats)>, String> {
+        self.stats(Some("eco"), "", None)
+    }
+
+    /// stats by speed
+    pub fn by_speed(&self) -> Result<Vec<(String, GameStats)>, String> {
+        self.stats(Some("speed"), "", None)
+    }
+}
@@ -0,0 +1,211 @@
+use log::{info, log_enabled, Level};
+
+use licoricedev::client::Lichess;
+use licoricedev::errors::LichessError;
+
+use crate::ponder::append_move;
+
+/// time kept in reserve when retrying a move submission, in milliseconds
+pub const SUBMIT_RESERVE_TIME: usize = 500;
+
+/// first retry delay of a move submission, doubled on every retry, in milliseconds
+pub const SUBMIT_RETRY_DELAY: usize = 250;
+
+/// max time to wait for the game state confirming a submitted move, in milliseconds
+pub const SUBMIT_CONFIRM_TIMEOUT: usize = 5000;
+
+/// min budget of a move submission, in milliseconds
+pub const SUBMIT_MIN_BUDGET: usize = 100;
+
+/// budget of a move submission, the bot's time less the time spent choosing the move,
+/// but at least SUBMIT_MIN_BUDGET
+pub fn submit_budget(bot_time: usize, think_time: usize) -> usize {
+    bot_time.saturating_sub(think_time).max(SUBMIT_MIN_BUDGET)
+}
+
+/// move submission error
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubmitError {
+    /// worth retrying ( network, rate limit, server error ), with suggested wait in milliseconds
+    Transient(String, Option<usize>),
+    /// retrying cannot help ( not your turn, game over, illegal move )
+    Permanent(String),
+}
+
+/// classify lichess error of a move submission
+pub fn classify_submit_error(err: &LichessError) -> SubmitError {
+    let message = err.to_string();
+
+    match err {
+        LichessError::RateLimited(retry_after) => {
+            SubmitError::Transient(message, retry_after.map(|secs| secs * 1000))
+        }
+        LichessError::Request(_) | LichessError::IO(_) => SubmitError::Transient(message, None),
+        LichessError::StatusCode(code, _) if *code >= 500 => SubmitError::Transient(message, None),
+        _ => SubmitError::Permanent(message),
+    }
+}
+
+/// move submitted to lichess, waiting for confirmation by a game state
+#[derive(Debug, Clone)]
+pub struct Submission {
+    /// move in uci notation
+    pub uci: String,
+    /// game moves before the move
+    pub moves: String,
+    /// time left on the bot's clock after the move was chosen, in milliseconds
+    pub budget: usize,
+    /// errors of attempts, not yet taken
+    pub errors: Vec<SubmitError>,
+    /// move was accepted by the api
+    pub accepted: bool,
+    /// move was rejected permanently
+    pub rejected: bool,
+    /// number of times the move was submitted again for lack of confirmation
+    pub resubmissions: usize,
+    /// time of last submission
+    pub submitted_at: std::time::Instant,
+    /// move offers or accepts a draw
+    pub offering_draw: bool,
+}
+
+/// implementation of submission
+impl Submission {
+    /// create submission
+    pub fn new<U, M>(uci: U, moves: M, budget: usize) -> Submission
+    where
+        U: core::fmt::Display,
+        M: core::fmt::Display,
+    {
+        Submission {
+            uci: uci.to_string(),
+            moves: moves.to_string(),
+            budget,
+            errors: vec![],
+            accepted: false,
+            rejected: false,
+            resubmissions: 0,
+            submitted_at: std::time::Instant::now(),
+            offering_draw: false,
+        }
+    }
+
+    /// set offering draw and return self
+    pub fn offering_draw(mut self, offering_draw: bool) -> Submission {
+        self.offering_draw = offering_draw;
+
+        self
+    }
+
+    /// game moves after the move
+    pub fn expected_moves(&self) -> String {
+        append_move(&self.moves, &self.uci)
+    }
+
+    /// true if game moves show the move registered
+    pub fn confirmed_by<T>(&self, moves: T) -> bool
+    where
+        T: core::fmt::Display,
+    {
+        let moves = moves.to_string();
+        let expected = self.expected_moves();
+
+        (moves == expected) || moves.starts_with(&format!("{} ", expected))
+    }
+
+    /// submit move, retrying transient errors while the budget allows
+    pub async fn submit<T>(
+        &mut self,
+        lichess: &Lichess,
+        game_id: T,
+        offering_draw: bool,
+        max_attempts: usize,
+    ) where
+        T: core::fmt::Display,
+    {
+        let game_id = game_id.to_string();
+
+        let start = std::time::Instant::now();
+
+        let mut delay = SUBMIT_RETRY_DELAY;
+
+        let budget = match self.budget > self.submitted_at.elapsed().as_millis() as usize {
+            true => self.budget - self.submitted_at.elapsed().as_millis() as usize,
+            _ => 0,
+        };
+
+        self.submitted_at = start;
+        self.budget = budget;
+        self.accepted = false;
+
+        for attempt in 1..=max_attempts.max(1) {
+            let result = lichess
+                .make_a_bot_move(game_id.as_str(), self.uci.as_str(), offering_draw)
+                .await;
+
+            if log_enabled!(Level::Info) {
+                info!(
+                    "make move {} attempt {} result {:?}",
+                    self.uci, attempt, result
+                );
+            }
+
+            let err = match result {
+                Ok(_) => {
+                    self.accepted = true;
+
+                    return;
+                }
+                Err(err) => classify_submit_error(&err),
+            };
+
+            self.errors.push(err.to_owned());
+
+            let wait = match err {
+                SubmitError::Permanent(_) => {
+                    self.rejected = true;
+
+                    return;
+                }
+                SubmitError::Transient(_, retry_after) => retry_after.unwrap_or(delay),
+            };
+
+            if !self.can_retry(start.elapsed().as_millis() as usize, wait) {
+                if log_enabled!(Level::Info) {
+                    info!("no time left for retrying move {}", self.uci);
+                }
+
+                return;
+            }
+
+            tokio::time::sleep(tokio::time::Duration::from_millis(wait as u64)).await;
+
+            delay *= 2;
+        }
+    }
+
+    /// true if the move can be submitted again after waiting, elapsed milliseconds after the
+    /// first attempt, keeping the reserve time on the clock
+    pub fn can_retry(&self, elapsed: usize, wait: usize) -> bool {
+        elapsed + wait + SUBMIT_RESERVE_TIME <= self.budget
+    }
+
+    /// take errors of attempts
+    pub fn take_errors(&mut self) -> Vec<SubmitError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// time left to wait for confirmation, in milliseconds
+    pub fn confirm_wait(&self) -> usize {
+        let timeout = SUBMIT_CONFIRM_TIMEOUT
+            .min(self.budget / 4)
+            .max(SUBMIT_RETRY_DELAY);
+
+        let elapsed = self.submitted_at.elapsed().as_millis() as usize;
+
+        match timeout > elapsed {
+            true => timeout - elapsed,
+            _ => 0,
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use log::{debug, info, log_enabled, Level};
+
+use shakmaty::uci::Uci;
+use shakmaty::{Chess, Setup};
+
+use shakmaty_syzygy::{Dtz, Syzygy, Tablebase, Wdl};
+
+use uciengine::analysis::Score;
+
+/// centipawn score of a tablebase win
+pub const TABLEBASE_WIN_CP: i32 = 20000;
+
+/// tablebase move of a position
+#[derive(Debug, Clone, PartialEq)]
+pub struct TablebaseMove {
+    /// move in uci notation
+    pub uci: String,
+    /// win, draw or loss of the position for the side to move
+    pub wdl: Wdl,
+    /// distance to zeroing of the position after the move, for the opponent
+    pub dtz: Dtz,
+}
+
+/// implementation of tablebase move
+impl TablebaseMove {
+    /// score for the side to move, wins and losses saved by the 50 move rule are draws
+    pub fn score(&self) -> Score {
+        Score::Cp(wdl_cp(self.wdl))
+    }
+}
+
+/// centipawn score of wdl, wins and losses saved by the 50 move rule are draws
+pub fn wdl_cp(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => TABLEBASE_WIN_CP,
+        Wdl::Loss => -TABLEBASE_WIN_CP,
+        _ => 0,
+    }
+}
+
+/// syzygy tablebase, tables are read lazily from their directories when probing
+#[derive(Debug)]
+pub struct SyzygyTablebase {
+    /// tables
+    pub tables: Tablebase<Chess>,
+    /// number of table files
+    pub num_tables: usize,
+    /// max number of pieces of probed positions
+    pub max_pieces: usize,
+}
+
+/// implementation of syzygy tablebase
+impl SyzygyTablebase {
+    /// create tablebase from directories separated by ;, fails if a directory cannot be read
+    /// or there are no tables
+    pub fn new<T>(dirs: T) -> Result<SyzygyTablebase, String>
+    where
+        T: core::fmt::Display,
+    {
+        let mut tables = Tablebase::new();
+
+        let mut num_tables = 0;
+
+        for dir in dirs.to_string().split(';').map(|dir| dir.trim()) {
+            if !dir.is_empty() {
+                num_tables += tables
+                    .add_directory(dir)
+                    .map_err(|err| format!("{} : {}", dir, err))?;
+            }
+        }
+
+        if num_tables == 0 {
+            return Err(format!("no syzygy tables in {}", dirs));
+        }
+
+        if log_enabled!(Level::Info) {
+            info!(
+                "syzygy tablebase {} loaded with {} tables",
+                dirs, num_tables
+            );
+        }
+
+        Ok(SyzygyTablebase {
+            tables,
+            num_tables,
+            max_pieces: <Chess as Syzygy>::MAX_PIECES,
+        })
+    }
+
+    /// set max pieces and return self
+    pub fn max_pieces(mut self, max_pieces: usize) -> SyzygyTablebase {
+        self.max_pieces = max_pieces;
+
+        self
+    }
+
+    /// true if position has few enough pieces and no castling rights for probing
+    pub fn probable(&self, pos: &Chess) -> bool {
+        (pos.board().occupied().count() <= self.max_pieces) && pos.castling_rights().is_empty()
+    }
+
+    /// win, draw or loss of position for the side to move, None if the position cannot be probed
+    pub fn wdl(&self, pos: &Chess) -> Option<Wdl> {
+        if !self.probable(pos) {
+            return None;
+        }
+
+        match self.tables.probe_wdl(pos) {
+            Ok(wdl) => Some(wdl),
+            Err(err) => {
+                if log_enabled!(Level::Debug) {
+                    debug!("syzygy wdl probe failed : {}", err);
+                }
+
+                None
+            }
+        }
+    }
+
+    /// true if position is drawn, also when a win or loss is saved by the 50 move rule,
+    /// None if the position cannot be probed
+    pub fn is_draw(&self, pos: &Chess) -> Option<bool> {
+        self.wdl(pos).map(|wdl| wdl_cp(wdl) == 0)
+    }
+
+    /// dtz optimal move of position, None if the position cannot be probed or is over
+    pub fn best_move(&self, pos: &Chess) -> Option<TablebaseMove> {
+        let wdl = self.wdl(pos)?;
+
+        match self.tables.best_move(pos) {
+            Ok(Some((m, dtz))) => Some(TablebaseMove {
+                uci: Uci::from_standard(&m).to_string(),
+                wdl,
+                dtz,
+            }),
+            Ok(None) => None,
+            Err(err) => {
+                if log_enabled!(Level::Debug) {
+                    debug!("syzygy dtz probe failed : {}", err);
+                }
+
+                None
+            }
+        }
+    }
+}
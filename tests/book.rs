@@ -0,0 +1,86 @@
+use lichessbot::book::*;
+use lichessbot::repertoire::*;
+use rand::prelude::*;
+
+/// candidates with weight and performance
+fn candidates() -> Vec<BookCandidate> {
+    [("d2d4", 30, 40), ("e2e4", 50, 55), ("g1f3", 20, 70)]
+        .iter()
+        .map(|(uci, weight, perf)| BookCandidate {
+            uci: uci.to_string(),
+            weight: *weight,
+            perf: Some(*perf),
+        })
+        .collect()
+}
+
+/// ucis of selections of policy with seed
+fn selections(policy: BookPolicy, seed: u64) -> Vec<String> {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    (0..20)
+        .filter_map(|_| policy.select(&candidates(), &mut rng))
+        .map(|candidate| candidate.uci)
+        .collect()
+}
+
+#[test]
+fn seeded_selection_is_reproducible() {
+    for name in ["weighted", "score", "uniform", "mixed:50", "softmax:0.2"] {
+        let policy = BookPolicy::new(BookSelection::from_name(name).unwrap());
+
+        assert_eq!(selections(policy, 7), selections(policy, 7), "{}", name);
+    }
+}
+
+#[test]
+fn best_and_zero_temperature_are_deterministic() {
+    let best = selections(BookPolicy::new(BookSelection::Best), 1);
+
+    assert!(best.iter().all(|uci| uci == "e2e4"));
+
+    let softmax = selections(BookPolicy::new(BookSelection::Softmax(0.0)), 1);
+
+    assert!(softmax.iter().all(|uci| uci == "g1f3"));
+}
+
+#[test]
+fn moves_below_min_score_are_avoided() {
+    let policy = BookPolicy::new(BookSelection::Uniform).min_perf(Some(50));
+
+    let ucis = selections(policy, 3);
+
+    assert_eq!(ucis.len(), 20);
+    assert!(!ucis.contains(&"d2d4".to_string()));
+
+    let policy = BookPolicy::new(BookSelection::Best).min_perf(Some(80));
+
+    assert!(selections(policy, 3).is_empty());
+}
+
+#[test]
+fn repertoire_replies_by_color_and_leaves_on_deviation() {
+    let repertoire = Repertoire::new()
+        .lines(true, "1. e4 e5 2. Nf3; 1. e4 c5 2. c3")
+        .lines(false, "1.e4 c5 2. Nf3 d6;d2d4 g8f6 c2c4 e7e6");
+
+    assert_eq!(repertoire.get_move(true, ""), Some("e2e4".to_string()));
+    assert_eq!(
+        repertoire.get_move(true, "e2e4 c7c5"),
+        Some("c2c3".to_string())
+    );
+    assert_eq!(repertoire.get_move(true, "e2e4 e7e6"), None);
+
+    assert_eq!(repertoire.get_move(false, "e2e4"), Some("c7c5".to_string()));
+    assert_eq!(
+        repertoire.get_move(false, "e2e4 c7c5 g1f3"),
+        Some("d7d6".to_string())
+    );
+    assert_eq!(
+        repertoire.get_move(false, "d2d4 g8f6 c2c4"),
+        Some("e7e6".to_string())
+    );
+    assert_eq!(repertoire.get_move(false, "c2c4"), None);
+
+    assert!(Repertoire::parse_line("1. e4 e4").is_err());
+}
@@ -0,0 +1,128 @@
+use lichessbot::botapi::*;
+use licoricedev::errors::LichessError;
+use licoricedev::models::board::BoardState;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// game state line with moves and extra fields
+fn game_state(moves: &str, extra: &str) -> String {
+    format!(
+        "{{\"type\":\"gameState\",\"moves\":\"{}\",\"wtime\":60000,\"btime\":60000,\"winc\":0,\
+         \"binc\":0,\"wdraw\":false,\"bdraw\":false,\"status\":\"started\"{}}}",
+        moves, extra
+    )
+}
+
+/// answer one request on a local port with status and body, the base url and a handle
+/// resolving to the request head are returned
+async fn serve_once(status: &str, body: String) -> (String, tokio::task::JoinHandle<String>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+    let base = format!("http://{}", listener.local_addr().unwrap());
+
+    let response = format!(
+        "HTTP/1.1 {}\r\ncontent-type: application/x-ndjson\r\nconnection: close\r\n\r\n{}",
+        status, body
+    );
+
+    let handle = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+
+        let mut head = vec![];
+        let mut buffer = [0u8; 1024];
+
+        while !String::from_utf8_lossy(&head).contains("\r\n\r\n") {
+            match socket.read(&mut buffer).await {
+                Ok(0) | Err(_) => break,
+                Ok(read) => head.extend_from_slice(&buffer[..read]),
+            }
+        }
+
+        let _ = socket.write_all(response.as_bytes()).await;
+
+        String::from_utf8_lossy(&head).to_string()
+    });
+
+    (base, handle)
+}
+
+#[test]
+fn takeback_proposals_are_taken_out_of_game_states() {
+    let line = game_state("e2e4 e7e5", ",\"btakeback\":true");
+
+    // the lichess client model rejects the unknown field
+    assert!(serde_json::from_str::<BoardState>(&line).is_err());
+
+    let event = GameEvent::parse(&line).unwrap();
+
+    assert!(event.btakeback && !event.wtakeback);
+    assert!(matches!(event.event, BoardState::GameState(state) if state.moves == "e2e4 e7e5"));
+
+    let event = GameEvent::parse(game_state("e2e4", "")).unwrap();
+
+    assert!(!event.btakeback && !event.wtakeback);
+
+    let event = GameEvent::parse(
+        "{\"type\":\"chatLine\",\"username\":\"alice\",\"text\":\"hi\",\"room\":\"player\"}",
+    )
+    .unwrap();
+
+    assert!(matches!(event.event, BoardState::ChatLine(line) if line.text == "hi"));
+}
+
+#[tokio::test]
+async fn game_stream_skips_keep_alive_lines() {
+    let body = format!(
+        "{}\n\n{}\n\n{}",
+        game_state("e2e4", ""),
+        game_state("e2e4 e7e5", ""),
+        game_state("e2e4 e7e5", ",\"wtakeback\":true")
+    );
+
+    let (base, head) = serve_once("200 OK", body).await;
+
+    let mut stream = BotApi::new("token")
+        .base(base)
+        .stream_game("abcdefgh")
+        .await
+        .unwrap();
+
+    let mut events = vec![];
+
+    while let Some(event) = stream.next_event().await.unwrap() {
+        events.push(event);
+    }
+
+    assert_eq!(events.len(), 3);
+    assert!(!events[1].wtakeback && events[2].wtakeback);
+    assert!(head
+        .await
+        .unwrap()
+        .starts_with("GET /api/bot/game/stream/abcdefgh HTTP/1.1"));
+}
+
+#[tokio::test]
+async fn takeback_answer_is_posted_with_token() {
+    let (base, head) = serve_once("200 OK", "{\"ok\":true}".to_string()).await;
+
+    let api = BotApi::new("token").base(base);
+
+    assert!(api.handle_takeback("abcdefgh", true).await.is_ok());
+
+    let head = head.await.unwrap();
+
+    assert!(head.starts_with("POST /api/bot/game/abcdefgh/takeback/yes HTTP/1.1"));
+    assert!(head.to_lowercase().contains("authorization: bearer token"));
+
+    let (base, head) = serve_once("400 Bad Request", "{}".to_string()).await;
+
+    let api = BotApi::new("token").base(base);
+
+    assert!(matches!(
+        api.handle_takeback("abcdefgh", false).await,
+        Err(LichessError::StatusCode(400, _))
+    ));
+    assert!(head
+        .await
+        .unwrap()
+        .starts_with("POST /api/bot/game/abcdefgh/takeback/no HTTP/1.1"));
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use lichessbot::chat::*;
+use lichessbot::game::*;
+use lichessbot::movesource::*;
+use uciengine::analysis::Score;
+
+/// record of a blitz game of the bot as white
+fn record() -> GameRecord {
+    GameRecord::new(common::game("alice", Some(1500)))
+}
+
+#[test]
+fn greeting_is_rendered_in_all_rooms() {
+    let config = ChatConfig::new()
+        .greeting("Hi {opponent} ( {opponent_rating} ), {bot} plays {speed} with {engine}")
+        .rooms("player");
+
+    let chat = GameChat::new(config, "bot", "stockfish");
+
+    assert_eq!(
+        chat.greeting(&record()),
+        vec![(
+            "player".to_string(),
+            "Hi alice ( 1500 ), bot plays blitz with stockfish".to_string()
+        )]
+    );
+    assert!(chat.goodbye(&record()).is_empty());
+}
+
+#[test]
+fn commands_are_answered_with_rate_limit() {
+    let config = ChatConfig::new().rate_limit(60000, 20);
+
+    let mut chat = GameChat::new(config, "bot", "stockfish");
+
+    chat.move_played(
+        &MoveProposal::new("e2e4", "engine")
+            .score(Score::Cp(35))
+            .depth(12),
+    );
+
+    assert_eq!(chat.answer("bot", "!eval", "player"), None);
+    assert_eq!(chat.answer("alice", "hello", "player"), None);
+    assert_eq!(
+        chat.answer("alice", "!eval", "spectator"),
+        Some(("spectator".to_string(), "eval +0.35 depth 12".to_string()))
+    );
+    assert_eq!(chat.answer("alice", "!engine", "player"), None);
+}
+
+#[test]
+fn eval_is_refused_in_player_room() {
+    let mut chat = GameChat::new(ChatConfig::new(), "bot", "stockfish");
+
+    chat.move_played(
+        &MoveProposal::new("e2e4", "engine")
+            .score(Score::Cp(35))
+            .depth(12),
+    );
+
+    assert_eq!(
+        chat.answer("alice", "!eval", "player"),
+        Some((
+            "player".to_string(),
+            "no eval in the player room".to_string()
+        ))
+    );
+}
@@ -0,0 +1,30 @@
+// not every test binary uses every helper
+#![allow(dead_code)]
+
+use lichessbot::game::*;
+
+/// player with name and rating
+pub fn player(name: &str, rating: Option<u16>) -> Player {
+    Player {
+        name: name.to_string(),
+        rating,
+        title: None,
+        ai_level: None,
+    }
+}
+
+/// rated 3+2 blitz game of the bot rated 2000 as white against opponent, created now
+pub fn game(opponent: &str, rating: Option<u16>) -> GameInfo {
+    GameInfo {
+        id: "abcdefgh".to_string(),
+        white: player("bot", Some(2000)),
+        black: player(opponent, rating),
+        bot_white: true,
+        speed: "blitz".to_string(),
+        rated: true,
+        variant: "standard".to_string(),
+        initial_fen: "startpos".to_string(),
+        time_control: Some((180000, 2000)),
+        created_at: chrono::Utc::now(),
+    }
+}
@@ -0,0 +1,690 @@
+mod common;
+
+use lichessbot::analysis::*;
+use lichessbot::book::*;
+use lichessbot::game::*;
+use lichessbot::learn::*;
+use lichessbot::lichessbot::BotState;
+use lichessbot::movesource::*;
+use lichessbot::ponder::*;
+use uciengine::uciengine::*;
+
+/// spawn the scripted fake engine
+fn fake_engine() -> std::sync::Arc<UciEngine> {
+    UciEngine::new(env!("CARGO_BIN_EXE_lichessbot-fakeengine"))
+}
+
+/// go job on moves from starting position
+fn go_job<T>(moves: T) -> GoJob
+where
+    T: core::fmt::Display,
+{
+    GoJob::new()
+        .pos_startpos()
+        .pos_moves(moves)
+        .tc(Timecontrol::default())
+}
+
+#[tokio::test]
+async fn default_answer_is_first_legal_move() {
+    let engine = fake_engine();
+
+    let result = engine.go(go_job("e2e4")).await.unwrap();
+
+    assert_eq!(result.bestmove, Some("a7a5".to_string()));
+    assert_eq!(result.ponder, Some("a2a3".to_string()));
+
+    engine.quit();
+}
+
+#[tokio::test]
+async fn scripted_answers_are_used_in_order() {
+    let engine = fake_engine();
+
+    let result = engine
+        .go(go_job("").uci_opt(
+            "Script",
+            "info depth 12 score cp 31 pv e2e4 e7e5|bestmove e2e4 ponder e7e5;bestmove d2d4",
+        ))
+        .await
+        .unwrap();
+
+    assert_eq!(result.bestmove, Some("e2e4".to_string()));
+    assert_eq!(result.ponder, Some("e7e5".to_string()));
+    assert_eq!(result.ai.depth, 12);
+
+    let result = engine.go(go_job("")).await.unwrap();
+
+    assert_eq!(result.bestmove, Some("d2d4".to_string()));
+    assert_eq!(result.ponder, None);
+
+    engine.quit();
+}
+
+#[tokio::test]
+async fn scripted_delay_is_thinking_time() {
+    let engine = fake_engine();
+
+    let start = std::time::Instant::now();
+
+    let result = engine
+        .go(go_job("").uci_opt("Script", "delay 150|bestmove g1f3"))
+        .await
+        .unwrap();
+
+    assert_eq!(result.bestmove, Some("g1f3".to_string()));
+    assert!(start.elapsed().as_millis() >= 150);
+
+    engine.quit();
+}
+
+#[tokio::test]
+async fn ponderhit_returns_ponder_search_result() {
+    let engine = fake_engine();
+
+    let mut ponder = Ponder::new();
+
+    let result = engine
+        .go(go_job("").uci_opt(
+            "Script",
+            "bestmove e2e4 ponder e7e5;delay 100|bestmove g1f3 ponder b8c6",
+        ))
+        .await
+        .unwrap();
+
+    ponder.start(
+        &engine,
+        "",
+        result.bestmove.unwrap(),
+        result.ponder.unwrap(),
+        Timecontrol::default(),
+    );
+
+    assert_eq!(ponder.status("e2e4"), PonderStatus::Waiting);
+    assert_eq!(ponder.status("e2e4 e7e5"), PonderStatus::Hit);
+
+    let result = ponder.hit(&engine).await.unwrap();
+
+    assert_eq!(result.bestmove, Some("g1f3".to_string()));
+    assert_eq!(ponder.status("e2e4 e7e5 g1f3"), PonderStatus::Idle);
+
+    engine.quit();
+}
+
+#[tokio::test]
+async fn pondermiss_result_does_not_leak_into_next_search() {
+    let engine = fake_engine();
+
+    let mut ponder = Ponder::new();
+
+    let _ = engine
+        .go(go_job("").uci_opt(
+            "Script",
+            "bestmove e2e4 ponder e7e5;bestmove g1f3;bestmove d2d4",
+        ))
+        .await
+        .unwrap();
+
+    ponder.start(&engine, "", "e2e4", "e7e5", Timecontrol::default());
+
+    assert_eq!(ponder.status("e2e4 c7c5"), PonderStatus::Miss);
+
+    assert!(ponder.stop(&engine).await.is_ok());
+
+    assert!(!ponder.is_pending());
+
+    let result = engine.go(go_job("e2e4 c7c5")).await.unwrap();
+
+    assert_eq!(result.bestmove, Some("d2d4".to_string()));
+
+    engine.quit();
+}
+
+#[tokio::test]
+async fn ponder_search_not_stopping_is_an_error() {
+    let engine = fake_engine();
+
+    let mut ponder = Ponder::new().stop_timeout(200);
+
+    let _ = engine
+        .go(go_job("").uci_opt("Script", "bestmove e2e4 ponder e7e5;hang"))
+        .await
+        .unwrap();
+
+    ponder.start(&engine, "", "e2e4", "e7e5", Timecontrol::default());
+
+    assert_eq!(
+        ponder.stop(&engine).await,
+        Err("engine did not stop pondering within 200 ms".to_string())
+    );
+    assert!(!ponder.is_pending());
+
+    engine.quit();
+}
+
+#[tokio::test]
+async fn takeback_is_a_pondermiss() {
+    let mut ponder = Ponder::new();
+
+    ponder.pending = Some(PendingPonder {
+        moves: "e2e4 e7e5".to_string(),
+        bestmove: "g1f3".to_string(),
+        ponder: "b8c6".to_string(),
+    });
+
+    assert_eq!(ponder.status("e2e4 e7e5 g1f3"), PonderStatus::Waiting);
+    assert_eq!(ponder.status("e2e4"), PonderStatus::Miss);
+    assert_eq!(ponder.status("e2e4 e7e5"), PonderStatus::Miss);
+}
+
+/// move request after moves from starting position, bot plays white
+fn move_request_after(moves: &str) -> MoveRequest {
+    let mut pos = shakmaty::Chess::default();
+
+    for uci in moves.split_whitespace() {
+        let m = uci
+            .parse::<shakmaty::uci::Uci>()
+            .unwrap()
+            .to_move(&pos)
+            .unwrap();
+
+        shakmaty::Position::play_unchecked(&mut pos, &m);
+    }
+
+    MoveRequest {
+        game: common::game("player", None),
+        moves: moves.to_string(),
+        fen: shakmaty::fen::fen(&pos),
+        epd: shakmaty::fen::epd(&pos),
+        pos,
+        clock: Clock {
+            wtime: 60000,
+            winc: 0,
+            btime: 60000,
+            binc: 0,
+        },
+        status: "started".to_string(),
+    }
+}
+
+/// move request from starting position, bot plays white
+fn move_request() -> MoveRequest {
+    move_request_after("")
+}
+
+/// engine move source of the fake engine with script, logging commands to log file
+fn scripted_source(script: &str, log_file: &std::path::Path) -> EngineMoveSource {
+    let _ = std::fs::remove_file(log_file);
+
+    let mut uci_options = std::collections::HashMap::new();
+
+    uci_options.insert("Script".to_string(), script.to_string());
+    uci_options.insert("Log".to_string(), log_file.to_string_lossy().to_string());
+
+    EngineMoveSource::new(
+        env!("CARGO_BIN_EXE_lichessbot-fakeengine"),
+        uci_options,
+        std::sync::Arc::new(tokio::sync::Mutex::new(BotState::default())),
+    )
+}
+
+/// log file of a test in the temp dir
+fn log_file(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("lichessbot-{}-{}.log", name, std::process::id()))
+}
+
+/// commands logged by the fake engine, the log file is removed
+fn logged_commands(log_file: &std::path::Path) -> Vec<String> {
+    let commands = std::fs::read_to_string(log_file)
+        .unwrap()
+        .lines()
+        .map(|line| line.to_string())
+        .collect();
+
+    let _ = std::fs::remove_file(log_file);
+
+    commands
+}
+
+/// white time of a logged go command
+fn go_wtime(command: &str) -> Option<usize> {
+    let tokens: Vec<&str> = command.split_whitespace().collect();
+
+    tokens
+        .iter()
+        .position(|token| *token == "wtime")
+        .and_then(|index| tokens.get(index + 1))
+        .and_then(|wtime| wtime.parse().ok())
+}
+
+#[tokio::test]
+async fn move_source_ponderhit_plays_ponder_search_result() {
+    let log_file = log_file("ponderhit");
+
+    let mut source = scripted_source(
+        "bestmove e2e4 ponder e7e5;delay 100|bestmove g1f3 ponder b8c6",
+        &log_file,
+    );
+
+    let req = move_request();
+
+    source.start_game(&req.game).await;
+
+    assert_eq!(source.get_move(&req).await.unwrap().uci, "e2e4");
+
+    let req = move_request_after("e2e4 e7e5");
+
+    source.update(&req).await;
+
+    let proposal = source.get_move(&req).await.unwrap();
+
+    assert_eq!(proposal.uci, "g1f3");
+    assert_eq!(proposal.ponder, Some("b8c6".to_string()));
+
+    source.end_game().await;
+
+    let commands = logged_commands(&log_file);
+
+    assert!(commands.iter().any(|command| command == "ponderhit"));
+    assert_eq!(
+        commands
+            .iter()
+            .filter(|command| command.starts_with("go"))
+            .count(),
+        3
+    );
+}
+
+#[tokio::test]
+async fn move_source_pondermiss_is_stopped_on_update() {
+    let log_file = log_file("pondermiss");
+
+    let mut source = scripted_source(
+        "bestmove e2e4 ponder e7e5;bestmove g1f3;bestmove d2d4",
+        &log_file,
+    );
+
+    let req = move_request();
+
+    source.start_game(&req.game).await;
+
+    assert_eq!(source.get_move(&req).await.unwrap().uci, "e2e4");
+
+    let req = move_request_after("e2e4 c7c5");
+
+    source.update(&req).await;
+
+    let commands = logged_commands(&log_file);
+
+    assert_eq!(commands.last(), Some(&"stop".to_string()));
+
+    // the stopped ponder search used the second entry, the script goes on with the third
+    assert_eq!(source.get_move(&req).await.unwrap().uci, "d2d4");
+    assert!(source.take_incidents().is_empty());
+
+    source.end_game().await;
+}
+
+#[tokio::test]
+async fn move_source_searches_and_ponders_with_time_left() {
+    let log_file = log_file("time");
+
+    let mut source = scripted_source("delay 200|bestmove e2e4 ponder e7e5", &log_file);
+
+    let req = move_request();
+
+    source.start_game(&req.game).await;
+
+    assert_eq!(source.get_move(&req).await.unwrap().uci, "e2e4");
+
+    source.end_game().await;
+
+    let wtimes: Vec<usize> = logged_commands(&log_file)
+        .iter()
+        .filter(|command| command.starts_with("go"))
+        .filter_map(|command| go_wtime(command))
+        .collect();
+
+    assert_eq!(wtimes.len(), 2);
+    assert_eq!(wtimes[0], 60000);
+
+    // the ponder search gets the clock less the thinking time
+    assert!(wtimes[1] <= 59800 && wtimes[1] > 50000);
+}
+
+#[tokio::test]
+async fn engine_takes_over_when_out_of_book() {
+    let mut book = LearningBook::new().min_games(1);
+
+    book.learn("e2e4", true, 1.0, 20);
+
+    let books = BookList::new();
+
+    books.push(ScopedBook::new(
+        "learned",
+        OpeningBook::Learning(std::sync::Arc::new(std::sync::RwLock::new(book))),
+    ));
+
+    let mut uci_options = std::collections::HashMap::new();
+
+    uci_options.insert("Script".to_string(), "bestmove g1f3".to_string());
+
+    let mut sources: Vec<Box<dyn MoveSource>> = vec![
+        Box::new(BookMoveSource::new(books)),
+        Box::new(EngineMoveSource::new(
+            env!("CARGO_BIN_EXE_lichessbot-fakeengine"),
+            uci_options,
+            std::sync::Arc::new(tokio::sync::Mutex::new(BotState::default())),
+        )),
+    ];
+
+    let req = move_request();
+
+    let mut record = GameRecord::new(req.game.clone());
+
+    for source in sources.iter_mut() {
+        source.start_game(&req.game).await;
+    }
+
+    let proposal = select_move(&mut sources, &req, &mut record).await.unwrap();
+
+    assert_eq!(
+        (proposal.uci.as_str(), proposal.source.as_str()),
+        ("e2e4", "book")
+    );
+
+    let req = move_request_after("e2e4 e7e5");
+
+    let proposal = select_move(&mut sources, &req, &mut record).await.unwrap();
+
+    assert_eq!(
+        (proposal.uci.as_str(), proposal.source.as_str()),
+        ("g1f3", "engine")
+    );
+
+    for source in sources.iter_mut() {
+        source.end_game().await;
+    }
+}
+
+#[tokio::test]
+async fn crashing_engine_is_restarted_and_recorded() {
+    let mut uci_options = std::collections::HashMap::new();
+
+    uci_options.insert("Script".to_string(), "crash".to_string());
+
+    let mut source = EngineMoveSource::new(
+        env!("CARGO_BIN_EXE_lichessbot-fakeengine"),
+        uci_options,
+        std::sync::Arc::new(tokio::sync::Mutex::new(BotState::default())),
+    )
+    .max_restarts(1);
+
+    let req = move_request();
+
+    source.start_game(&req.game).await;
+
+    assert!(source.get_move(&req).await.is_none());
+
+    let kinds: Vec<IncidentKind> = source
+        .take_incidents()
+        .iter()
+        .map(|incident| incident.kind)
+        .collect();
+
+    assert_eq!(
+        kinds,
+        vec![
+            IncidentKind::EngineCrash,
+            IncidentKind::EngineRestart,
+            IncidentKind::EngineCrash
+        ]
+    );
+
+    source.end_game().await;
+}
+
+#[tokio::test]
+async fn illegal_ponder_reply_is_not_pondered() {
+    let req = move_request();
+
+    assert!(req.is_legal_reply("e2e4", "e7e5"));
+    assert!(!req.is_legal_reply("e2e4", "d2d4"));
+    assert!(!req.is_legal_reply("e2e5", "e7e5"));
+
+    let mut uci_options = std::collections::HashMap::new();
+
+    uci_options.insert(
+        "Script".to_string(),
+        "bestmove e2e4 ponder d2d4".to_string(),
+    );
+
+    let mut source = EngineMoveSource::new(
+        env!("CARGO_BIN_EXE_lichessbot-fakeengine"),
+        uci_options,
+        std::sync::Arc::new(tokio::sync::Mutex::new(BotState::default())),
+    );
+
+    source.start_game(&req.game).await;
+
+    let proposal = source.get_move(&req).await.unwrap();
+
+    assert_eq!(proposal.uci, "e2e4");
+    assert_eq!(proposal.ponder, None);
+
+    source.end_game().await;
+}
+
+#[tokio::test]
+async fn book_move_losing_eval_is_rejected() {
+    let mut uci_options = std::collections::HashMap::new();
+
+    uci_options.insert(
+        "Script".to_string(),
+        "info depth 4 score cp 40|bestmove e2e4;info depth 4 score cp 20|bestmove e7e5;\
+         info depth 4 score cp 40|bestmove e2e4;info depth 4 score cp 300|bestmove e7e5"
+            .to_string(),
+    );
+
+    let log_file =
+        std::env::temp_dir().join(format!("lichessbot-verify-{}.log", std::process::id()));
+
+    let _ = std::fs::remove_file(&log_file);
+
+    let mut verifier = BookVerifier::new(
+        env!("CARGO_BIN_EXE_lichessbot-fakeengine"),
+        uci_options,
+        4,
+        100,
+    )
+    .log_file(Some(log_file.to_string_lossy().to_string()));
+
+    let req = move_request();
+
+    assert!(verifier.verify(&req, "book", "e2e4").await);
+    assert!(!verifier.verify(&req, "book", "f2f3").await);
+
+    verifier.end_game();
+
+    assert_eq!(
+        std::fs::read_to_string(&log_file).unwrap(),
+        format!("{}\tf2f3\tbook\t340\n", req.epd)
+    );
+
+    let _ = std::fs::remove_file(&log_file);
+}
+
+#[tokio::test]
+async fn hung_verifier_engine_rejects_book_move() {
+    let mut uci_options = std::collections::HashMap::new();
+
+    uci_options.insert("Script".to_string(), "hang".to_string());
+
+    let mut verifier = BookVerifier::new(
+        env!("CARGO_BIN_EXE_lichessbot-fakeengine"),
+        uci_options,
+        4,
+        100,
+    );
+
+    let mut req = move_request();
+
+    req.clock.wtime = BOOK_VERIFY_MIN_TIME;
+
+    let start = std::time::Instant::now();
+
+    assert!(!verifier.verify(&req, "book", "e2e4").await);
+    assert!(start.elapsed().as_millis() < BOOK_VERIFY_MIN_TIME as u128);
+    assert!(verifier.engine.is_none());
+}
+
+#[tokio::test]
+async fn analysis_judges_moves_by_centipawn_loss() {
+    let mut uci_options = std::collections::HashMap::new();
+
+    uci_options.insert(
+        "Script".to_string(),
+        "info depth 4 score cp 30|bestmove e2e4;info depth 4 score cp 40|bestmove g1f3;\
+         info depth 4 score cp 200|bestmove e7e5"
+            .to_string(),
+    );
+
+    let analyzer = GameAnalyzer::new(
+        env!("CARGO_BIN_EXE_lichessbot-fakeengine"),
+        uci_options,
+        4,
+        "analysis",
+    );
+
+    let mut record = GameRecord::new(move_request().game);
+
+    record.moves = "e2e4 e7e5 d2d4".to_string();
+    record.decision(MoveProposal::new("e2e4", "book").decision(0, 10));
+    record.decision(MoveProposal::new("d2d4", "engine").decision(2, 1200));
+
+    let analysis = analyzer.analyze(&record).await.unwrap();
+
+    let losses: Vec<(i32, MoveJudgement)> = analysis
+        .moves
+        .iter()
+        .map(|m| (m.loss, m.judgement))
+        .collect();
+
+    assert_eq!(
+        losses,
+        vec![(0, MoveJudgement::Good), (240, MoveJudgement::Mistake)]
+    );
+    assert_eq!(analysis.out_of_book, Some((2, "engine".to_string())));
+    assert_eq!(analysis.count(MoveJudgement::Mistake), 1);
+    assert!(analysis
+        .to_json()
+        .contains("\"out_of_book\":{\"ply\":2,\"source\":\"engine\"}"));
+    assert_eq!(
+        analysis.notes()[&2].text,
+        "out of book, engine takes over , mistake , best g1f3 , loss 240 cp"
+    );
+}
+
+/// move proposed by an engine picking among multi pv lines with the scripted answer
+async fn multipv_proposal(script: &str) -> MoveProposal {
+    let mut uci_options = std::collections::HashMap::new();
+
+    uci_options.insert("Script".to_string(), script.to_string());
+
+    let mut source = EngineMoveSource::new(
+        env!("CARGO_BIN_EXE_lichessbot-fakeengine"),
+        uci_options,
+        std::sync::Arc::new(tokio::sync::Mutex::new(BotState::default())),
+    )
+    .pick(Some(lichessbot::opponent::MovePick {
+        margin: 100,
+        temperature: 50.0,
+    }));
+
+    let req = move_request();
+
+    source.start_game(&req.game).await;
+
+    let proposal = source.get_move(&req).await.unwrap();
+
+    source.end_game().await;
+
+    proposal
+}
+
+#[tokio::test]
+async fn multipv_pick_plays_a_line_within_margin() {
+    let proposal = multipv_proposal(
+        "info depth 8 multipv 1 score cp 40 pv e2e4 e7e5|\
+         info depth 8 multipv 2 score cp -300 pv f2f3 e7e5|bestmove e2e4 ponder e7e5",
+    )
+    .await;
+
+    assert_eq!(proposal.uci, "e2e4");
+    assert_eq!(proposal.depth, Some(8));
+    assert!(matches!(
+        proposal.score,
+        Some(uciengine::analysis::Score::Cp(40))
+    ));
+    assert_eq!(proposal.ponder, Some("e7e5".to_string()));
+
+    // the only line within the margin is not the best move, pondering follows its pv
+    let proposal = multipv_proposal(
+        "info depth 8 multipv 1 score cp 40 pv e2e4 e7e5|\
+         info depth 8 multipv 2 score cp 500 pv d2d4 d7d5|bestmove e2e4 ponder e7e5",
+    )
+    .await;
+
+    assert_eq!(proposal.uci, "d2d4");
+    assert_eq!(proposal.ponder, Some("d7d5".to_string()));
+
+    // without the best line the best move is played
+    let proposal =
+        multipv_proposal("info depth 8 multipv 2 score cp 500 pv d2d4 d7d5|bestmove e2e4").await;
+
+    assert_eq!(proposal.uci, "e2e4");
+}
+
+#[tokio::test]
+async fn hung_engine_times_out_like_a_crash() {
+    let mut uci_options = std::collections::HashMap::new();
+
+    uci_options.insert("Script".to_string(), "delay 3000|bestmove e2e4".to_string());
+
+    let mut source = EngineMoveSource::new(
+        env!("CARGO_BIN_EXE_lichessbot-fakeengine"),
+        uci_options,
+        std::sync::Arc::new(tokio::sync::Mutex::new(BotState::default())),
+    )
+    .max_restarts(0);
+
+    let mut req = move_request();
+
+    req.clock.wtime = 2000;
+
+    assert_eq!(engine_timeout(&req), ENGINE_TIMEOUT_MIN);
+
+    source.start_game(&req.game).await;
+
+    let start = std::time::Instant::now();
+
+    assert!(source.get_move(&req).await.is_none());
+    assert!(start.elapsed().as_millis() < 2000);
+
+    let incidents = source.take_incidents();
+
+    assert_eq!(incidents.len(), 1);
+    assert_eq!(incidents[0].kind, IncidentKind::EngineCrash);
+    assert!(incidents[0]
+        .message
+        .contains("did not answer within 1000 ms"));
+
+    source.end_game().await;
+}
+
+#[test]
+fn missing_engine_is_not_spawned() {
+    assert!(!engine_exists("./no-such-engine"));
+    assert!(!engine_exists("no-such-engine-in-path"));
+    assert!(engine_exists(env!("CARGO_BIN_EXE_lichessbot-fakeengine")));
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use lichessbot::game::*;
+
+#[test]
+fn outcome_of_status_and_winner() {
+    assert_eq!(GameOutcome::from_status("started", None, true), None);
+
+    let win = GameOutcome::from_status("mate", Some("white"), true).unwrap();
+
+    assert_eq!(win.termination, Termination::Mate);
+    assert_eq!(win.result, Some(BotResult::Win));
+    assert_eq!(win.pgn_result(), "1-0");
+
+    let loss = GameOutcome::from_status("outoftime", Some("white"), false).unwrap();
+
+    assert_eq!(loss.result, Some(BotResult::Loss));
+
+    let draw = GameOutcome::from_status("stalemate", None, false).unwrap();
+
+    assert_eq!(draw.result, Some(BotResult::Draw));
+    assert_eq!(draw.pgn_result(), "1/2-1/2");
+
+    let aborted = GameOutcome::from_status("aborted", None, true).unwrap();
+
+    assert_eq!(aborted.termination, Termination::Aborted);
+    assert_eq!(aborted.result, None);
+    assert_eq!(aborted.pgn_result(), "*");
+}
+
+#[test]
+fn annotated_pgn_of_game() {
+    use chrono::TimeZone;
+    use lichessbot::archive::*;
+    use uciengine::analysis::Score;
+
+    let mut record = GameRecord::new(GameInfo {
+        white: common::player("alice", Some(1500)),
+        black: common::player("bot", None),
+        bot_white: false,
+        created_at: chrono::Utc.with_ymd_and_hms(2021, 1, 31, 12, 0, 0).unwrap(),
+        ..common::game("alice", Some(1500))
+    });
+
+    record.moves = "e2e4 e7e5".to_string();
+    record.status = "resign".to_string();
+    record.winner = Some("black".to_string());
+    record.clocks = vec![Some(179500), Some(181200)];
+    record.decision(MoveDecision {
+        ply: 1,
+        uci: "e7e5".to_string(),
+        source: "engine".to_string(),
+        score: Some(Score::Cp(35)),
+        depth: Some(12),
+        time: 1250,
+    });
+
+    let outcome = record.outcome().unwrap();
+
+    let pgn = game_pgn(&record, &outcome, "stockfish", None);
+
+    assert_eq!(pgn_header(&pgn, "Date"), Some("2021.01.31".to_string()));
+    assert_eq!(pgn_header(&pgn, "Result"), Some("0-1".to_string()));
+    assert_eq!(pgn_header(&pgn, "BlackElo"), Some("?".to_string()));
+    assert_eq!(pgn_header(&pgn, "TimeControl"), Some("180+2".to_string()));
+    assert_eq!(pgn_header(&pgn, "Engine"), Some("stockfish".to_string()));
+    assert!(pgn.ends_with(
+        "\n\n1. e4 { [%clk 0:02:59] } e5\n{ engine depth 12 [%eval -0.35] [%emt 0:00:01.2] [%clk 0:03:01] } 0-1\n"
+    ));
+
+    assert_eq!(
+        PgnArchive::new("games").path(&record.info),
+        std::path::Path::new("games/2021-01/2021-01-31.pgn")
+    );
+}
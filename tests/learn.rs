@@ -0,0 +1,69 @@
+use lichessbot::learn::*;
+
+/// epd of the starting position
+const START_EPD: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -";
+
+#[test]
+fn statistics_decay_before_each_game() {
+    let mut book = LearningBook::new().decay(0.5);
+
+    book.learn("e2e4 e7e5 g1f3", true, 1.0, 20);
+    book.learn("e2e4 c7c5", true, 0.0, 20);
+
+    let e4 = book.positions[START_EPD]["e2e4"];
+
+    assert_eq!(e4.games, 2);
+    assert_eq!(e4.plays, 1.5);
+    assert_eq!(e4.score, 0.5);
+    assert_eq!(e4.perf(), 33);
+
+    // only the bot's moves within max ply are learned
+    assert_eq!(book.positions.len(), 2);
+
+    book.learn("d2d4 d7d5", false, 0.5, 1);
+
+    assert_eq!(book.positions.len(), 2);
+}
+
+#[test]
+fn candidates_need_min_games() {
+    let mut book = LearningBook::new().decay(1.0).min_games(2);
+
+    book.learn("e2e4", true, 1.0, 20);
+    book.learn("e2e4", true, 0.5, 20);
+    book.learn("d2d4", true, 1.0, 20);
+
+    let candidates = book.candidates(START_EPD);
+
+    assert_eq!(candidates.len(), 1);
+    assert_eq!(candidates[0].uci, "e2e4");
+    assert_eq!(candidates[0].weight, 200);
+    assert_eq!(candidates[0].perf, Some(75));
+    assert!(book.candidates("8/8/8/8/8/8/8/8 w - -").is_empty());
+}
+
+#[test]
+fn saved_book_loads_back() {
+    let dir = std::env::temp_dir().join(format!("lichessbot-learn-{}", std::process::id()));
+
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let path = dir.join("book.learn").to_string_lossy().to_string();
+
+    let mut book = LearningBook::new().load(&path).unwrap();
+
+    assert!(book.positions.is_empty());
+
+    book.learn("e2e4 e7e5 g1f3 b8c6", true, 0.5, 20);
+    book.learn("d2d4 d7d5", true, 1.0, 20);
+    book.save().unwrap();
+
+    assert!(!std::path::Path::new(&format!("{}.tmp", path)).exists());
+
+    let loaded = LearningBook::new().load(&path).unwrap();
+
+    assert_eq!(loaded.positions, book.positions);
+    assert_eq!(loaded.content(), book.content());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,168 @@
+mod common;
+
+use lichessbot::opponent::*;
+use lichessbot::store::GameStats;
+
+use common::game;
+
+/// name value pair
+fn option(name: &str, value: &str) -> (String, String) {
+    (name.to_string(), value.to_string())
+}
+
+#[test]
+fn options_by_rating_band_user_and_history() {
+    let options = OpponentOptions::new()
+        .bands("..-200:Contempt=50; -200..200:Contempt=0 ;200..:Contempt=-20,Threads=8")
+        .users("Alice:Contempt=100,Skill Level=10")
+        .min_history(4);
+
+    assert_eq!(options.bands.len(), 3);
+
+    assert_eq!(
+        options.options_for(&game("bob", Some(1700)), None),
+        vec![option("Contempt", "50")]
+    );
+    assert_eq!(
+        options.options_for(&game("bob", Some(2200)), None),
+        vec![option("Contempt", "-20"), option("Threads", "8")]
+    );
+    assert!(options.options_for(&game("bob", None), None).is_empty());
+    assert_eq!(
+        options.options_for(&game("alice", Some(2000)), None),
+        vec![
+            option("Contempt", "0"),
+            option("Contempt", "100"),
+            option("Skill Level", "10")
+        ]
+    );
+
+    // beating a higher rated opponent most of the time makes them a weaker one
+    let history = GameStats {
+        wins: 9,
+        draws: 0,
+        losses: 1,
+    };
+
+    assert_eq!(
+        options.rating_difference(&game("bob", Some(2300)), Some(&history)),
+        Some(-381)
+    );
+    assert_eq!(
+        options.options_for(&game("bob", Some(2300)), Some(&history)),
+        vec![option("Contempt", "50")]
+    );
+    assert_eq!(
+        options.rating_difference(&game("bob", Some(2300)), Some(&GameStats::default())),
+        Some(300)
+    );
+}
+
+#[test]
+fn handicap_limits_strength_in_casual_games_against_humans() {
+    let handicap = Handicap::new()
+        .mode(HandicapMode::from_name("Elo").unwrap())
+        .elo_offset(-100);
+
+    let mut casual = game("bob", Some(1600));
+
+    casual.rated = false;
+
+    assert_eq!(
+        handicap.uci_options(&casual),
+        vec![
+            option("UCI_LimitStrength", "true"),
+            option("UCI_Elo", "1500")
+        ]
+    );
+    assert!(handicap.uci_options(&game("bob", Some(1600))).is_empty());
+
+    casual.black.title = Some("BOT".to_string());
+
+    assert!(!handicap.applies(&casual));
+
+    casual.black.title = None;
+    casual.black.rating = Some(900);
+
+    assert_eq!(
+        handicap.uci_options(&casual)[1],
+        option("UCI_Elo", &HANDICAP_MIN_ELO.to_string())
+    );
+    assert_eq!(
+        handicap
+            .clone()
+            .mode(HandicapMode::Skill)
+            .uci_options(&casual),
+        vec![option("Skill Level", "0")]
+    );
+
+    casual.black.rating = None;
+
+    assert_eq!(
+        handicap
+            .clone()
+            .mode(HandicapMode::Skill)
+            .uci_options(&casual),
+        vec![option("Skill Level", "4")]
+    );
+
+    let multipv = Handicap::new()
+        .mode(HandicapMode::MultiPv)
+        .multipv(3, 80, 40.0);
+
+    assert_eq!(multipv.uci_options(&casual), vec![option("MultiPV", "3")]);
+    assert_eq!(
+        multipv.move_pick(&casual),
+        Some(MovePick {
+            margin: 80,
+            temperature: 40.0
+        })
+    );
+    assert_eq!(multipv.move_pick(&game("bob", None)), None);
+    assert_eq!(handicap.move_pick(&casual), None);
+    assert_eq!(HandicapMode::from_name("weaker"), None);
+}
+
+#[test]
+fn move_pick_stays_within_margin() {
+    use rand::SeedableRng;
+    use uciengine::analysis::Score;
+
+    let line = |multipv: usize, uci: &str, cp: i32| PvLine {
+        multipv,
+        uci: uci.to_string(),
+        score: Score::Cp(cp),
+        depth: 10,
+        pv: uci.to_string(),
+        ponder: None,
+    };
+
+    let lines = vec![
+        line(1, "e2e4", 40),
+        line(2, "d2d4", 30),
+        line(3, "f2f3", -150),
+    ];
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+
+    let pick = MovePick {
+        margin: 100,
+        temperature: 50.0,
+    };
+
+    let picked: Vec<String> = (0..200)
+        .map(|_| pick.select(&lines, &mut rng).unwrap().uci.to_owned())
+        .collect();
+
+    assert!(picked.iter().any(|uci| uci == "e2e4"));
+    assert!(picked.iter().any(|uci| uci == "d2d4"));
+    assert!(!picked.iter().any(|uci| uci == "f2f3"));
+
+    let greedy = MovePick {
+        margin: 100,
+        temperature: 0.0,
+    };
+
+    assert_eq!(greedy.select(&lines, &mut rng).unwrap().uci, "e2e4");
+    assert!(greedy.select(&[], &mut rng).is_none());
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use lichessbot::game::*;
+use lichessbot::policy::*;
+use uciengine::analysis::Score;
+
+/// game of the bot as white
+fn game(speed: &str, rated: bool) -> GameInfo {
+    GameInfo {
+        speed: speed.to_string(),
+        rated,
+        ..common::game("alice", Some(1500))
+    }
+}
+
+#[test]
+fn resigns_after_consecutive_low_evals() {
+    let policy = ResignPolicy::new()
+        .after(3, -1000)
+        .speed_scores("bullet:-600");
+
+    let mut resign = GameResign::new(policy.clone());
+
+    let blitz = game("blitz", true);
+
+    assert!(!resign.update(&blitz, Some(&Score::Cp(-1200))));
+    assert!(!resign.update(&blitz, Some(&Score::Cp(-1500))));
+    assert!(!resign.update(&blitz, None));
+    assert!(!resign.update(&blitz, Some(&Score::Cp(-700))));
+    assert!(!resign.update(&blitz, Some(&Score::Cp(-1200))));
+    assert!(!resign.update(&blitz, Some(&Score::Mate(-5))));
+    assert!(resign.update(&blitz, Some(&Score::Cp(-2000))));
+
+    let mut resign = GameResign::new(policy.clone());
+
+    let bullet = game("bullet", true);
+
+    for _ in 0..2 {
+        assert!(!resign.update(&bullet, Some(&Score::Cp(-700))));
+    }
+
+    assert!(resign.update(&bullet, Some(&Score::Cp(-700))));
+
+    let mut resign = GameResign::new(policy.casual(false));
+
+    for _ in 0..5 {
+        assert!(!resign.update(&game("blitz", false), Some(&Score::Mate(-1))));
+    }
+}
+
+#[test]
+fn draws_by_level_eval_after_min_move_or_tablebase() {
+    let mut draw = GameDraw::new(DrawPolicy::new().after(2, 20, 30));
+
+    assert!(!draw.update(20, Some(&Score::Cp(0)), None));
+    assert!(!draw.update(22, Some(&Score::Cp(0)), None));
+    assert!(!draw.update(70, Some(&Score::Cp(50)), None));
+    assert!(!draw.update(72, Some(&Score::Cp(10)), None));
+    assert!(draw.update(74, Some(&Score::Cp(-10)), None));
+    assert!(!draw.update(76, None, None));
+
+    assert!(draw.update(40, Some(&Score::Cp(300)), Some(true)));
+    assert!(!draw.update(40, Some(&Score::Cp(0)), Some(false)));
+
+    let mut draw = GameDraw::new(DrawPolicy::new().accept_offer(true, true));
+
+    assert!(!draw.offering_draw(80, false, true));
+    assert!(draw.offering_draw(80, true, false));
+    assert!(!draw.offering_draw(82, true, false));
+    assert!(draw.offering_draw(84, true, true));
+    assert!(draw.offering_draw(100, true, false));
+}
+
+#[test]
+fn takebacks_by_policy_and_detection() {
+    assert_eq!(
+        TakebackPolicy::from_name("Users:Alice, bob"),
+        Some(TakebackPolicy::Users(vec![
+            "alice".to_string(),
+            "bob".to_string()
+        ]))
+    );
+    assert_eq!(TakebackPolicy::from_name("always"), None);
+
+    assert!(!TakebackPolicy::Decline.accepts(&game("blitz", false)));
+    assert!(TakebackPolicy::Casual.accepts(&game("blitz", false)));
+    assert!(!TakebackPolicy::Casual.accepts(&game("blitz", true)));
+    assert!(TakebackPolicy::from_name("users:alice")
+        .unwrap()
+        .accepts(&game("blitz", true)));
+
+    assert_eq!(taken_back("e2e4 e7e5 g1f3 b8c6", "e2e4 e7e5"), Some(2));
+    assert_eq!(taken_back("e2e4 e7e5", "e2e4 e7e5 g1f3"), None);
+    assert_eq!(taken_back("e2e4 e7e5", "d2d4"), None);
+    assert_eq!(taken_back("e2e4", ""), Some(1));
+}
@@ -0,0 +1,58 @@
+use lichessbot::polyglot::*;
+use shakmaty::uci::Uci;
+use shakmaty::{Chess, Position};
+
+/// position after space separated uci moves from the starting position
+fn position(moves: &str) -> Chess {
+    moves.split_whitespace().fold(Chess::default(), |pos, uci| {
+        let m = uci.parse::<Uci>().unwrap().to_move(&pos).unwrap();
+
+        pos.play(&m).unwrap()
+    })
+}
+
+#[test]
+fn keys_match_polyglot_reference_keys() {
+    for (moves, key) in [
+        ("", 0x463b96181691fc9c),
+        ("e2e4", 0x823c9b50fd114196),
+        ("e2e4 d7d5", 0x0756b94461c50fb0),
+        ("e2e4 d7d5 e4e5", 0x662fafb965db29d4),
+        // en passant on f6 can be captured
+        ("e2e4 d7d5 e4e5 f7f5", 0x22a48b5a8e47ff78),
+        // king moves lose castling rights
+        ("e2e4 d7d5 e4e5 f7f5 e1e2", 0x652a607ca3f242c1),
+        ("e2e4 d7d5 e4e5 f7f5 e1e2 e8f7", 0x00fdd303c946bdd9),
+        ("a2a4 b7b5 h2h4 b5b4 c2c4", 0x3c8123ea7b067637),
+        ("a2a4 b7b5 h2h4 b5b4 c2c4 b4c3 a1a3", 0x5c3f9b829b279560),
+    ] {
+        assert_eq!(polyglot_key(&position(moves)), key, "after {:?}", moves);
+    }
+}
+
+#[test]
+fn en_passant_without_capturing_pawn_is_not_hashed() {
+    // the en passant square of d7d5 cannot be captured on, so the key does not depend on it
+    assert_eq!(
+        polyglot_key(&position("e2e4 d7d5")),
+        polyglot_key(&position("e2e4 g8f6 g1f3 f6g8 f3g1 d7d5"))
+    );
+}
+
+#[test]
+fn castling_is_encoded_as_king_takes_rook() {
+    let pos = position("e2e4 e7e5 g1f3 b8c6 f1c4 g8f6");
+
+    let mv = encode_move(&pos, "e1g1").unwrap();
+
+    assert_eq!(mv, (4 << 6) | 7);
+
+    let entry = PolyglotEntry {
+        key: polyglot_key(&pos),
+        mv,
+        weight: 1,
+        learn: 0,
+    };
+
+    assert_eq!(entry.uci(&pos), "e1g1");
+}
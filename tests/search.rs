@@ -0,0 +1,48 @@
+use lichessbot::search::*;
+use shakmaty::fen::Fen;
+use shakmaty::{CastlingMode, Chess};
+use uciengine::analysis::Score;
+
+/// position of fen
+fn position(fen: &str) -> Chess {
+    fen.parse::<Fen>()
+        .unwrap()
+        .position(CastlingMode::Standard)
+        .unwrap()
+}
+
+#[test]
+fn finds_mate_in_one() {
+    let pos = position("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+
+    let result = Searcher::new().max_depth(4).search(&pos, 5000).unwrap();
+
+    assert_eq!(result.uci, "a1a8");
+    assert!(matches!(result.uci_score(), Score::Mate(1)));
+}
+
+#[test]
+fn takes_hanging_queen() {
+    let pos = position("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1");
+
+    let result = Searcher::new().max_depth(3).search(&pos, 5000).unwrap();
+
+    assert_eq!(result.uci, "d2d5");
+    assert!(result.score > 300);
+}
+
+#[test]
+fn search_stops_in_time() {
+    let start = std::time::Instant::now();
+
+    let result = Searcher::new().search(&Chess::default(), 200).unwrap();
+
+    assert!(start.elapsed().as_millis() < 1000);
+    assert!(result.depth >= 1);
+}
+
+#[test]
+fn move_time_keeps_a_reserve() {
+    assert_eq!(move_time(60000, 0), 1500);
+    assert_eq!(move_time(1000, 2000), 250);
+}
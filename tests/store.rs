@@ -0,0 +1,73 @@
+mod common;
+
+use lichessbot::game::*;
+use lichessbot::store::*;
+
+/// record of a finished game of the bot as white
+fn record(id: &str, opponent: &str, speed: &str, status: &str, winner: Option<&str>) -> GameRecord {
+    let mut record = GameRecord::new(GameInfo {
+        id: id.to_string(),
+        speed: speed.to_string(),
+        ..common::game(opponent, Some(1500))
+    });
+
+    record.moves = "e2e4 e7e5".to_string();
+    record.status = status.to_string();
+    record.winner = winner.map(|winner| winner.to_string());
+    record.decision(lichessbot::movesource::MoveProposal::new("e2e4", "book").decision(0, 10));
+
+    record
+}
+
+#[test]
+fn stats_of_stored_games() {
+    let store = GameStore::open_in_memory().unwrap();
+
+    for record in [
+        record("game1", "alice", "blitz", "mate", Some("white")),
+        record("game2", "Alice", "bullet", "resign", Some("black")),
+        record("game3", "bob", "blitz", "draw", None),
+        record("game4", "bob", "blitz", "aborted", None),
+    ] {
+        store
+            .add_game(&record, &record.outcome().unwrap(), None)
+            .unwrap();
+    }
+
+    assert_eq!(
+        store.totals().unwrap(),
+        GameStats {
+            wins: 1,
+            draws: 1,
+            losses: 1
+        }
+    );
+    assert_eq!(store.opponent("ALICE").unwrap().games(), 2);
+    assert_eq!(store.opponent("bob").unwrap().score(), Some(0.5));
+    assert_eq!(store.opponent("carol").unwrap().score(), None);
+
+    let by_speed = store.by_speed().unwrap();
+
+    assert!(by_speed.contains(&(
+        "blitz".to_string(),
+        GameStats {
+            wins: 1,
+            draws: 1,
+            losses: 0
+        }
+    )));
+    assert_eq!(store.by_opening().unwrap()[0].0, "?");
+
+    store
+        .add_challenge(&ChallengeDecision {
+            id: "challenge".to_string(),
+            challenger: Some("alice".to_string()),
+            challenger_rating: Some(1500),
+            speed: "correspondence".to_string(),
+            variant: "standard".to_string(),
+            rated: true,
+            accepted: false,
+            reasons: vec!["wrong speed ( correspondence )".to_string()],
+        })
+        .unwrap();
+}
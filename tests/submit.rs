@@ -0,0 +1,107 @@
+use lichessbot::submit::*;
+use licoricedev::errors::{APIError, LichessError};
+
+/// lichess error answered by the api with message
+fn api_error(message: &str) -> LichessError {
+    LichessError::API(
+        serde_json::from_str::<APIError>(&format!("{{\"error\":\"{}\"}}", message)).unwrap(),
+    )
+}
+
+#[test]
+fn submit_errors_are_classified() {
+    let transient_wait = |err: &LichessError| match classify_submit_error(err) {
+        SubmitError::Transient(_, wait) => Some(wait),
+        _ => None,
+    };
+
+    assert_eq!(
+        transient_wait(&LichessError::RateLimited(Some(2))),
+        Some(Some(2000))
+    );
+    assert_eq!(transient_wait(&LichessError::RateLimited(None)), Some(None));
+    assert_eq!(
+        transient_wait(&LichessError::IO(std::io::Error::new(
+            std::io::ErrorKind::ConnectionReset,
+            "reset"
+        ))),
+        Some(None)
+    );
+    assert_eq!(
+        transient_wait(&LichessError::StatusCode(503, "unavailable".to_string())),
+        Some(None)
+    );
+
+    assert_eq!(
+        classify_submit_error(&LichessError::StatusCode(400, "bad request".to_string())),
+        SubmitError::Permanent("status code 400: bad request".to_string())
+    );
+    assert_eq!(
+        classify_submit_error(&api_error("Not your turn, or game already over")),
+        SubmitError::Permanent(
+            "lichess error: error: Not your turn, or game already over".to_string()
+        )
+    );
+    assert!(matches!(
+        classify_submit_error(&LichessError::ParseJSON(
+            serde_json::from_str::<u32>("move").unwrap_err()
+        )),
+        SubmitError::Permanent(_)
+    ));
+}
+
+#[test]
+fn submission_is_confirmed_by_game_moves_after_the_move() {
+    let submission = Submission::new("e7e5", "e2e4", 60000);
+
+    assert_eq!(submission.expected_moves(), "e2e4 e7e5");
+    assert!(submission.confirmed_by("e2e4 e7e5"));
+    assert!(submission.confirmed_by("e2e4 e7e5 g1f3"));
+    assert!(!submission.confirmed_by("e2e4"));
+    assert!(!submission.confirmed_by("e2e4 e7e6"));
+    assert!(!submission.confirmed_by("e2e4 e7e5q"));
+
+    assert!(Submission::new("e2e4", "", 60000).confirmed_by("e2e4"));
+}
+
+#[test]
+fn confirm_wait_is_capped_by_budget_and_times_out() {
+    let wait = Submission::new("e2e4", "", 60000).confirm_wait();
+
+    assert!(wait <= SUBMIT_CONFIRM_TIMEOUT && wait > SUBMIT_CONFIRM_TIMEOUT - 100);
+
+    // a quarter of the budget, but at least the retry delay
+    let wait = Submission::new("e2e4", "", 8000).confirm_wait();
+
+    assert!(wait <= 2000 && wait > 1900);
+
+    let wait = Submission::new("e2e4", "", 400).confirm_wait();
+
+    assert!(wait <= SUBMIT_RETRY_DELAY && wait > SUBMIT_RETRY_DELAY - 100);
+
+    let mut submission = Submission::new("e2e4", "", 60000);
+
+    submission.submitted_at = std::time::Instant::now()
+        .checked_sub(std::time::Duration::from_millis(
+            SUBMIT_CONFIRM_TIMEOUT as u64 + 1000,
+        ))
+        .unwrap();
+
+    assert_eq!(submission.confirm_wait(), 0);
+}
+
+#[test]
+fn retries_stop_when_thinking_used_up_the_budget() {
+    assert_eq!(submit_budget(10000, 3000), 7000);
+    assert_eq!(submit_budget(2000, 2500), SUBMIT_MIN_BUDGET);
+
+    let submission = Submission::new("e2e4", "", submit_budget(10000, 800));
+
+    assert!(submission.can_retry(0, SUBMIT_RETRY_DELAY));
+    assert!(!submission.can_retry(9000, SUBMIT_RETRY_DELAY));
+
+    // with the whole clock of 1200 ms a retry would still fit
+    let submission = Submission::new("e2e4", "", submit_budget(1200, 800));
+
+    assert!(!submission.can_retry(0, SUBMIT_RETRY_DELAY));
+}